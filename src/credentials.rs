@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::Arc;
+
+use serde::Deserialize;
+use zeroize::Zeroize;
+
+use crate::datas::request::ChatLogin;
+use crate::datas::request::KeyPolicy;
+use crate::error::Error;
+use crate::secret::Secret;
+
+pub const ENV_API_KEY: &str = "OPENAI_API_KEY";
+pub const ENV_ORG_ID: &str = "OPENAI_ORG_ID";
+/// Profile to read from the config file, "default" when unset
+pub const ENV_PROFILE: &str = "OPENAI_PROFILE";
+/// Config file to read instead of the default one
+pub const ENV_CONFIG_FILE: &str = "XTGPTR_CONFIG";
+pub const DEFAULT_PROFILE: &str = "default";
+
+type Lookup = Arc<dyn Fn(&str) -> Option<String> + Send + Sync>;
+
+/// Where [`CredentialChain::resolve`] found the key
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum CredentialSource {
+    Explicit,
+    /// Name of the environment variable
+    Env(String),
+    /// Profile of a config file
+    Profile { path: PathBuf, name: String },
+    /// Command whose output was the key
+    Helper(String)
+}
+
+impl fmt::Display for CredentialSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CredentialSource::Explicit => write!(f, "explicit value"),
+            CredentialSource::Env(name) => write!(f, "environment variable {}", name),
+            CredentialSource::Profile { path, name } => write!(f, "profile {} of {}", name, path.display()),
+            CredentialSource::Helper(command) => write!(f, "credential helper `{}`", command)
+        }
+    }
+}
+
+/// A profile of the config file, e.g.
+/// ```toml
+/// [profiles.default]
+/// api_key = "sk-..."
+/// organization = "org-..."
+///
+/// [profiles.ci]
+/// credential_helper = "vault read -field=key secret/openai"
+/// ```
+#[derive(Deserialize, Clone, Default, PartialEq, Eq, Debug)]
+pub struct Profile {
+    api_key: Option<Secret>,
+    organization: Option<String>,
+    /// Command printing the key, used when `api_key` is not set
+    credential_helper: Option<String>
+}
+
+impl Profile {
+    pub fn get_api_key(&self) -> Option<&Secret> {
+        self.api_key.as_ref()
+    }
+
+    pub fn get_organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    pub fn get_credential_helper(&self) -> Option<&str> {
+        self.credential_helper.as_deref()
+    }
+}
+
+#[derive(Deserialize, Default, Debug)]
+struct ConfigFile {
+    #[serde(default)]
+    profiles: HashMap<String, Profile>
+}
+
+/// Read the profile `name` of the TOML config file at `path`
+pub fn load_profile(path: impl AsRef<Path>, name: &str) -> Result<Profile, Error> {
+    let path = path.as_ref();
+    let text = std::fs::read_to_string(path)?;
+    let file: ConfigFile = toml::from_str(&text)
+        .map_err(|e| Error::Decode(format!("{}: {}", path.display(), e)))?;
+    file.profiles.get(name).cloned()
+        .ok_or_else(|| Error::validation("profile", format!("no profile {} in {}", name, path.display())))
+}
+
+/// `$XTGPTR_CONFIG`, else `~/.config/xtgptr/config.toml`
+pub fn default_config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os(ENV_CONFIG_FILE) {
+        return Some(PathBuf::from(path));
+    }
+    std::env::var_os("HOME").or_else(|| std::env::var_os("USERPROFILE"))
+        .map(|home| PathBuf::from(home).join(".config").join("xtgptr").join("config.toml"))
+}
+
+/// Run `command` through the shell and take its trimmed output as the key
+fn run_helper(command: &str) -> Result<Secret, Error> {
+    let output = match cfg!(windows) {
+        true => Command::new("cmd").args(["/C", command]).output()?,
+        false => Command::new("sh").args(["-c", command]).output()?
+    };
+    if !output.status.success() {
+        return Err(Error::validation("credential_helper", format!("`{}` failed with {}", command, output.status)));
+    }
+    let mut stdout = output.stdout;
+    let key = Secret::new(String::from_utf8_lossy(&stdout).trim());
+    stdout.zeroize();
+    match key.is_empty() {
+        true => Err(Error::validation("credential_helper", format!("`{}` printed no key", command))),
+        false => Ok(key)
+    }
+}
+
+/// Sources tried in order until one gives a key: explicit value, `OPENAI_API_KEY` / `OPENAI_ORG_ID`,
+/// a profile of the config file, then a credential helper command \
+/// The profile file is optional, a missing file is skipped. A source that is found but broken
+/// (unreadable file, failing helper, key rejected by the policy) is an error, not skipped.
+#[derive(Clone)]
+pub struct CredentialChain {
+    explicit: Option<(Secret, Option<String>)>,
+    env: bool,
+    config_path: Option<PathBuf>,
+    profile: Option<String>,
+    helper: Option<String>,
+    policy: KeyPolicy,
+    lookup: Lookup
+}
+
+impl Default for CredentialChain {
+    fn default() -> Self {
+        CredentialChain {
+            explicit: None,
+            env: true,
+            config_path: default_config_path(),
+            profile: None,
+            helper: None,
+            policy: KeyPolicy::default(),
+            lookup: Arc::new(|name| std::env::var(name).ok())
+        }
+    }
+}
+
+impl CredentialChain {
+    pub fn new() -> CredentialChain {
+        CredentialChain::default()
+    }
+
+    /// Key (and organization) to use before anything else
+    pub fn explicit(mut self, key: impl Into<Secret>, organization: Option<String>) -> Self {
+        self.explicit = Some((key.into(), organization));
+        self
+    }
+
+    /// Whether to read `OPENAI_API_KEY` / `OPENAI_ORG_ID`, true by default
+    pub fn env(mut self, enabled: bool) -> Self {
+        self.env = enabled;
+        self
+    }
+
+    /// Config file to read profiles from, None to skip profiles
+    pub fn config_path(mut self, path: Option<PathBuf>) -> Self {
+        self.config_path = path;
+        self
+    }
+
+    /// Profile to read, defaults to `$OPENAI_PROFILE`, then "default"
+    pub fn profile(mut self, name: impl Into<String>) -> Self {
+        self.profile = Some(name.into());
+        self
+    }
+
+    /// Command printing the key, tried last
+    pub fn helper(mut self, command: impl Into<String>) -> Self {
+        self.helper = Some(command.into());
+        self
+    }
+
+    /// Policy the resolved key must pass
+    pub fn policy(mut self, policy: KeyPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    #[cfg(test)]
+    fn lookup(mut self, lookup: impl Fn(&str) -> Option<String> + Send + Sync + 'static) -> Self {
+        self.lookup = Arc::new(lookup);
+        self
+    }
+
+    /// Find a key, reporting where it came from
+    pub fn resolve(&self) -> Result<(ChatLogin<String>, CredentialSource), Error> {
+        let (key, organization, source) = self.find()?;
+        let login = ChatLogin::from_secret(&key, organization, self.policy.clone())
+            .map_err(|e| Error::validation("auth", format!("key from {} rejected: {}", source, e)))?;
+        Ok((login, source))
+    }
+
+    fn find(&self) -> Result<(Secret, Option<String>, CredentialSource), Error> {
+        if let Some((key, organization)) = &self.explicit {
+            return Ok((key.clone(), organization.clone(), CredentialSource::Explicit));
+        }
+        let env = |name: &str| (self.lookup)(name).filter(|v| !v.trim().is_empty());
+        if self.env {
+            if let Some(key) = env(ENV_API_KEY) {
+                return Ok((Secret::new(key), env(ENV_ORG_ID), CredentialSource::Env(ENV_API_KEY.to_string())));
+            }
+        }
+        let mut tried = vec![String::from("explicit value")];
+        if self.env {
+            tried.push(format!("environment variable {}", ENV_API_KEY));
+        }
+        if let Some(path) = self.config_path.as_ref().filter(|p| p.exists()) {
+            let name = self.profile.clone().or_else(|| env(ENV_PROFILE)).unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+            let profile = load_profile(path, &name)?;
+            if let Some(key) = profile.api_key {
+                return Ok((key, profile.organization, CredentialSource::Profile { path: path.clone(), name }));
+            }
+            if let Some(command) = profile.credential_helper {
+                return Ok((run_helper(&command)?, profile.organization, CredentialSource::Helper(command)));
+            }
+            tried.push(format!("profile {} of {}", name, path.display()));
+        }
+        if let Some(command) = &self.helper {
+            return Ok((run_helper(command)?, None, CredentialSource::Helper(command.clone())));
+        }
+        Err(Error::validation("auth", format!("no credentials found, tried {}", tried.join(", "))))
+    }
+}
+
+impl ChatLogin<String> {
+    /// Resolve with the default [`CredentialChain`]: `OPENAI_API_KEY`, then the config file
+    pub fn from_env() -> Result<(ChatLogin<String>, CredentialSource), Error> {
+        CredentialChain::new().resolve()
+    }
+
+    /// Resolve from the profile `name` of the config file at `path` only
+    pub fn from_profile(path: impl Into<PathBuf>, name: impl Into<String>) -> Result<(ChatLogin<String>, CredentialSource), Error> {
+        CredentialChain::new().env(false).config_path(Some(path.into())).profile(name).resolve()
+    }
+
+    pub fn from_chain(chain: &CredentialChain) -> Result<(ChatLogin<String>, CredentialSource), Error> {
+        chain.resolve()
+    }
+}
+
+#[cfg(test)]
+mod credentials_tests {
+    use super::*;
+
+    const KEY: &str = "sk-proj-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX";
+    const OTHER: &str = "sk-YYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYYY";
+
+    fn config_file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("xtgptr-{}-{}.toml", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn empty_env() -> CredentialChain {
+        CredentialChain::new().config_path(None).lookup(|_| None)
+    }
+
+    #[test]
+    fn test_chain_order() {
+        let env = |name: &str| match name {
+            ENV_API_KEY => Some(String::from(KEY)),
+            ENV_ORG_ID => Some(String::from("org-env")),
+            _ => None
+        };
+        let (login, source) = empty_env().lookup(env).explicit(OTHER, None).resolve().unwrap();
+        assert_eq!((login.get_auth().expose(), source), (OTHER, CredentialSource::Explicit));
+        let (login, source) = empty_env().lookup(env).resolve().unwrap();
+        assert_eq!(login.get_auth().expose(), KEY);
+        assert_eq!(login.get_organization().map(String::as_str), Some("org-env"));
+        assert_eq!(source, CredentialSource::Env(String::from(ENV_API_KEY)));
+        let err = empty_env().lookup(env).env(false).resolve().err().unwrap();
+        assert!(err.to_string().contains("no credentials found"));
+        let err = empty_env().explicit("not-a-key", None).resolve().err().unwrap();
+        assert!(err.to_string().contains("explicit value"));
+    }
+
+    #[test]
+    fn test_profiles() {
+        let path = config_file("profiles", &format!("[profiles.default]\napi_key = \"{}\"\norganization = \"org-1\"\n\n[profiles.ci]\ncredential_helper = \"echo {}\"\n\n[profiles.empty]\n", KEY, OTHER));
+        let (login, source) = empty_env().config_path(Some(path.clone())).resolve().unwrap();
+        assert_eq!(login.get_auth().expose(), KEY);
+        assert_eq!(source, CredentialSource::Profile { path: path.clone(), name: String::from("default") });
+        let (login, source) = ChatLogin::from_profile(path.clone(), "ci").unwrap();
+        assert_eq!(login.get_auth().expose(), OTHER);
+        assert_eq!(source, CredentialSource::Helper(format!("echo {}", OTHER)));
+        let (_, source) = empty_env().config_path(Some(path.clone())).profile("empty").helper(format!("echo {}", KEY)).resolve().unwrap();
+        assert_eq!(source, CredentialSource::Helper(format!("echo {}", KEY)));
+        assert!(ChatLogin::from_profile(path.clone(), "missing").is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_helper_failure() {
+        assert!(empty_env().helper("exit 3").resolve().is_err());
+        assert!(empty_env().helper("true").resolve().is_err());
+    }
+}
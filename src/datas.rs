@@ -1,15 +1,26 @@
+pub mod builder;
+pub mod content;
+pub mod format;
 pub mod response;
+pub mod registry;
 pub mod request;
+pub mod tool;
+pub mod usage;
 
-/**
+/*
  * ======
  * GLOBAL VAR
  * ======
  */
 
-pub const POST_URL: &str = "https://api.openai.com/v1/chat/completions";
+pub const DEFAULT_BASE_URL: &str = "https://api.openai.com/v1";
+pub const CHAT_COMPLETIONS_PATH: &str = "/chat/completions";
 pub const AUTH_METHOD: &str = "Bearer";
 pub const AUTH_ORG: &str = "OpenAI-Organization";
+/// Header carrying the key on Azure OpenAI
+pub const AUTH_AZURE_KEY: &str = "api-key";
+/// Query parameter selecting the Azure OpenAI API version
+pub const AZURE_API_VERSION: &str = "api-version";
 pub const AUTH_CONTENT_TYPE: &str = "application/json";
 /// Max number of chat completion choices generated for each input message 
 const MAX_N: u32 = 1024;
\ No newline at end of file
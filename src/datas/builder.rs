@@ -0,0 +1,298 @@
+use std::ops::Deref;
+
+use super::format::ResponseFormat;
+use super::registry::Registry;
+use super::request::Body;
+use super::request::Message;
+use super::request::Models;
+use super::request::Roles;
+use super::request::StringOrArray;
+use super::request::Warning;
+use super::tool::Tool;
+use super::tool::ToolChoice;
+use crate::error::Error;
+
+/// Fluent way to write a [`Body`], from [`Body::builder`] \
+/// Every rejected parameter is kept and reported together by [`build`](BodyBuilder::build), with the rules spanning
+/// several fields: no messages, a tool message answering no call, JSON mode without the word "json" in the prompt. \
+/// Legal but dubious choices (temperature and top_p both set, several streamed choices, no user message, limits of the
+/// model) are warnings, they don't stop the build.
+#[derive(Debug)]
+pub struct BodyBuilder<Sentence> {
+    body: Body<Sentence>,
+    /// Set last, once every tool is declared
+    tool_choice: Option<ToolChoice>,
+    errors: Vec<Error>
+}
+
+impl<Sentence: AsRef<str>> BodyBuilder<Sentence> {
+    pub fn new(model: Models) -> BodyBuilder<Sentence> {
+        BodyBuilder { body: Body::new(model), tool_choice: None, errors: Vec::new() }
+    }
+
+    fn check(mut self, result: Result<(), Error>) -> Self {
+        if let Err(err) = result {
+            self.errors.push(err);
+        }
+        self
+    }
+
+    pub fn model(mut self, model: Models) -> Self {
+        self.body.set_models(model);
+        self
+    }
+
+    pub fn message(mut self, message: Message<Sentence>) -> Self {
+        self.body.add_message(message);
+        self
+    }
+
+    pub fn messages(mut self, messages: Vec<Message<Sentence>>) -> Self {
+        self.body.add_messages(messages);
+        self
+    }
+
+    pub fn system(self, content: Sentence) -> Self {
+        self.message(Message::new(Roles::System, content))
+    }
+
+    pub fn user(self, content: Sentence) -> Self {
+        self.message(Message::new(Roles::User, content))
+    }
+
+    pub fn assistant(self, content: Sentence) -> Self {
+        self.message(Message::new(Roles::Assistant, content))
+    }
+
+    pub fn temperature(mut self, temperature: f32) -> Self {
+        let result = self.body.set_temperature(temperature);
+        self.check(result)
+    }
+
+    pub fn top_p(mut self, top_p: f32) -> Self {
+        let result = self.body.set_top_p(top_p);
+        self.check(result)
+    }
+
+    pub fn n(mut self, n: u32) -> Self {
+        let result = self.body.set_n(n);
+        self.check(result)
+    }
+
+    pub fn stream(mut self, stream: bool) -> Self {
+        let result = self.body.set_stream(stream);
+        self.check(result)
+    }
+
+    pub fn stop(mut self, stop: StringOrArray<Sentence>) -> Self {
+        let result = self.body.set_stop(stop);
+        self.check(result)
+    }
+
+    pub fn max_tokens(mut self, max_tokens: u32) -> Self {
+        let result = self.body.set_max_tokens(max_tokens);
+        self.check(result)
+    }
+
+    pub fn presence_penalty(mut self, presence_penalty: f32) -> Self {
+        let result = self.body.set_presence_penalty(presence_penalty);
+        self.check(result)
+    }
+
+    pub fn frequency_penalty(mut self, frequency_penalty: f32) -> Self {
+        let result = self.body.set_frequency_penalty(frequency_penalty);
+        self.check(result)
+    }
+
+    pub fn logit_bias(mut self, token: u32, bias: i32) -> Self {
+        let result = self.body.add_logit_bias(token, bias);
+        self.check(result)
+    }
+
+    /// The `user` parameter, id of your end-user (not a user message, see [`user`](BodyBuilder::user))
+    pub fn end_user(mut self, user: Sentence) -> Self {
+        self.body.set_user(user);
+        self
+    }
+
+    pub fn tool(mut self, tool: Tool) -> Self {
+        let result = self.body.add_tool(tool);
+        self.check(result)
+    }
+
+    /// Checked at the build against every declared tool, whatever the order of the calls
+    pub fn tool_choice(mut self, tool_choice: ToolChoice) -> Self {
+        self.tool_choice = Some(tool_choice);
+        self
+    }
+
+    pub fn parallel_tool_calls(mut self, parallel_tool_calls: bool) -> Self {
+        self.body.set_parallel_tool_calls(parallel_tool_calls);
+        self
+    }
+
+    pub fn response_format(mut self, response_format: ResponseFormat) -> Self {
+        self.body.set_response_format(response_format);
+        self
+    }
+
+    /// [`build_with`](BodyBuilder::build_with) the built-in registry
+    pub fn build(self) -> Result<ValidBody<Sentence>, Error> {
+        self.build_with(Registry::builtin())
+    }
+
+    /// The body and its warnings, or [`Error::Invalid`] with every error and warning found \
+    /// The model limits are checked with [`Body::validate`] against `registry`.
+    pub fn build_with(mut self, registry: &Registry) -> Result<ValidBody<Sentence>, Error> {
+        if let Some(tool_choice) = self.tool_choice.take() {
+            let result = self.body.set_tool_choice(tool_choice);
+            self = self.check(result);
+        }
+        let BodyBuilder { body, mut errors, .. } = self;
+        let messages = body.get_messages();
+        if messages.is_empty() {
+            errors.push(Error::validation("messages", "at least one message is needed"));
+        }
+        for (i, message) in messages.iter().enumerate() {
+            let Some(id) = message.get_tool_call_id() else { continue };
+            if !messages[..i].iter().flat_map(Message::get_tool_calls).any(|call| call.get_id() == id) {
+                errors.push(Error::validation("messages", format!("tool message answers {}, which no earlier message called", id)));
+            }
+        }
+        if body.get_response_format() == Some(&ResponseFormat::JsonObject)
+            && !messages.iter().any(|m| m.get_content().to_text().to_lowercase().contains("json")) {
+            errors.push(Error::validation("response_format", "json_object needs the word json in the messages"));
+        }
+
+        let mut warnings = Vec::new();
+        if body.get_temperature().is_some() && body.get_top_p().is_some() {
+            warnings.push(Warning::new("top_p", "set temperature or top_p, not both"));
+        }
+        if body.get_n().unwrap_or(1) > 1 && body.get_stream() == Some(true) {
+            warnings.push(Warning::new("n", "the chunks of the streamed choices arrive interleaved"));
+        }
+        if !messages.is_empty() && !messages.iter().any(|m| m.get_role() == Roles::User) {
+            warnings.push(Warning::new("messages", "no user message"));
+        }
+        warnings.extend(body.validate(registry));
+
+        match errors.is_empty() {
+            true => Ok(ValidBody { body, warnings }),
+            false => Err(Error::Invalid { errors, warnings })
+        }
+    }
+}
+
+/// [`Body`] that passed [`BodyBuilder::build`], with its warnings \
+/// Derefs to the body, so `perform` and [`Client::chat`](crate::netreq::client::Client::chat) take it as is.
+#[derive(Clone, PartialEq, Debug)]
+pub struct ValidBody<Sentence> {
+    body: Body<Sentence>,
+    warnings: Vec<Warning>
+}
+
+impl<Sentence> ValidBody<Sentence> {
+    pub fn get_body(&self) -> &Body<Sentence> {
+        &self.body
+    }
+
+    pub fn get_warnings(&self) -> &[Warning] {
+        &self.warnings
+    }
+
+    pub fn into_body(self) -> Body<Sentence> {
+        self.body
+    }
+}
+
+impl<Sentence> Deref for ValidBody<Sentence> {
+    type Target = Body<Sentence>;
+
+    fn deref(&self) -> &Body<Sentence> {
+        &self.body
+    }
+}
+
+impl<Sentence> AsRef<Body<Sentence>> for ValidBody<Sentence> {
+    fn as_ref(&self) -> &Body<Sentence> {
+        &self.body
+    }
+}
+
+#[cfg(test)]
+mod builder_tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::datas::tool::ToolCall;
+
+    fn weather() -> Tool {
+        Tool::function("get_weather", "Weather of a city", json!({ "type": "object", "properties": { "city": { "type": "string" } } })).unwrap()
+    }
+
+    #[test]
+    fn test_build() {
+        let valid = Body::builder(Models::GPT4o)
+            .system("Be brief.")
+            .user("Weather in Paris?")
+            .tool_choice(ToolChoice::Function(String::from("get_weather")))
+            .tool(weather())
+            .temperature(0.2)
+            .max_tokens(100)
+            .build()
+            .unwrap();
+        assert!(valid.get_warnings().is_empty());
+        assert_eq!(valid.get_messages().len(), 2);
+        assert_eq!(valid.get_tool_choice(), Some(&ToolChoice::Function(String::from("get_weather"))));
+        assert_eq!(valid.into_body().get_temperature(), Some(0.2));
+    }
+
+    #[test]
+    fn test_build_violations() {
+        let err = Body::<&str>::builder(Models::GPT4o)
+            .temperature(3.0)
+            .n(0)
+            .logit_bias(42, 200)
+            .tool_choice(ToolChoice::Required)
+            .response_format(ResponseFormat::JsonObject)
+            .build()
+            .unwrap_err();
+        let Error::Invalid { errors, warnings } = &err else { panic!("{:?}", err) };
+        let fields: Vec<&str> = errors.iter().map(|e| match e {
+            Error::Validation { field, .. } => *field,
+            e => panic!("{:?}", e)
+        }).collect();
+        assert_eq!(fields, ["temperature", "n", "logit_bias", "tool_choice", "messages", "response_format"]);
+        assert!(warnings.is_empty());
+        assert!(err.to_string().starts_with("invalid body: invalid temperature: "));
+
+        let call = ToolCall::new("call_1", "get_weather", "{\"city\":\"Paris\"}");
+        let err = Body::builder(Models::GPT4o)
+            .user("Weather in Paris?")
+            .message(Message::tool_calls(None, vec![call]))
+            .message(Message::tool("call_1", "18°C"))
+            .message(Message::tool("call_2", "20°C"))
+            .build()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "invalid body: invalid messages: tool message answers call_2, which no earlier message called");
+    }
+
+    #[test]
+    fn test_build_warnings() {
+        let valid = Body::builder(Models::GPT4o)
+            .system("Answer in JSON.")
+            .temperature(0.5)
+            .top_p(0.9)
+            .n(2)
+            .stream(true)
+            .max_tokens(20_000)
+            .response_format(ResponseFormat::JsonObject)
+            .build()
+            .unwrap();
+        let fields: Vec<&str> = valid.get_warnings().iter().map(Warning::get_field).collect();
+        assert_eq!(fields, ["top_p", "n", "messages", "max_tokens"]);
+
+        let err = Body::<&str>::builder(Models::GPT4o).temperature(0.5).top_p(0.9).build().unwrap_err();
+        assert!(matches!(err, Error::Invalid { errors, warnings } if errors.len() == 1 && warnings[0].get_field() == "top_p"));
+    }
+}
@@ -0,0 +1,256 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+
+use crate::error::Error;
+
+/// The API takes images up to 20 MB
+pub const MAX_IMAGE_BYTES: usize = 20 * 1024 * 1024;
+
+/// Tokens an image costs at low detail, and roughly at high detail for a 1024x1024 picture
+const LOW_DETAIL_TOKENS: u32 = 85;
+const HIGH_DETAIL_TOKENS: u32 = 765;
+
+fn too_large(len: u64) -> Error {
+    Error::validation("image_url", format!("image of {} bytes is over the {} bytes limit", len, MAX_IMAGE_BYTES))
+}
+
+/// How closely the model looks at an image, low is cheaper (a fixed 85 tokens)
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Detail {
+    #[default]
+    Auto,
+    Low,
+    High
+}
+
+#[skip_serializing_none]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct ImageUrl {
+    /// http(s) url or `data:image/...;base64,` url
+    url: String,
+    /// default to auto
+    detail: Option<Detail>
+}
+
+impl ImageUrl {
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_detail(&self) -> Option<Detail> {
+        self.detail
+    }
+}
+
+/// One piece of a message made of several
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart<T> {
+    Text { text: T },
+    ImageUrl { image_url: ImageUrl }
+}
+
+/// MIME type of png, jpeg, gif and webp images, from their first bytes
+pub fn image_mime(bytes: &[u8]) -> Option<&'static str> {
+    match bytes {
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("image/png"),
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some("image/gif"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        _ => None
+    }
+}
+
+impl<T> ContentPart<T> {
+    pub fn text(text: T) -> ContentPart<T> {
+        ContentPart::Text { text }
+    }
+
+    /// Image the API downloads, or a `data:image/` url
+    pub fn image_url(url: impl Into<String>, detail: Option<Detail>) -> Result<ContentPart<T>, Error> {
+        let url = url.into();
+        if !["http://", "https://", "data:image/"].iter().any(|scheme| url.starts_with(scheme)) {
+            return Err(Error::validation("image_url", "url must be http(s) or a data:image/ url"));
+        }
+        Ok(ContentPart::ImageUrl { image_url: ImageUrl { url, detail } })
+    }
+
+    /// Image sent inline as a base64 data url, its type is found from its bytes
+    pub fn image_bytes(bytes: &[u8], detail: Option<Detail>) -> Result<ContentPart<T>, Error> {
+        if bytes.len() > MAX_IMAGE_BYTES {
+            return Err(too_large(bytes.len() as u64));
+        }
+        let mime = image_mime(bytes).ok_or_else(|| Error::validation("image_url", "image must be png, jpeg, gif or webp"))?;
+        ContentPart::image_url(format!("data:{};base64,{}", mime, STANDARD.encode(bytes)), detail)
+    }
+
+    /// [`image_bytes`](ContentPart::image_bytes) of a local file, refused from its size before it is read
+    pub fn image_file(path: impl AsRef<Path>, detail: Option<Detail>) -> Result<ContentPart<T>, Error> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        if len > MAX_IMAGE_BYTES as u64 {
+            return Err(too_large(len));
+        }
+        // a file growing meanwhile is read one byte past the limit, enough for image_bytes to refuse it
+        let mut bytes = Vec::with_capacity(len as usize);
+        file.take(MAX_IMAGE_BYTES as u64 + 1).read_to_end(&mut bytes)?;
+        ContentPart::image_bytes(&bytes, detail)
+    }
+
+    pub fn as_text(&self) -> Option<&T> {
+        match self {
+            ContentPart::Text { text } => Some(text),
+            _ => None
+        }
+    }
+
+    pub fn as_image(&self) -> Option<&ImageUrl> {
+        match self {
+            ContentPart::ImageUrl { image_url } => Some(image_url),
+            _ => None
+        }
+    }
+}
+
+/// Content of a message: a string, or text and images \
+/// Serialized as the string itself or as the array of parts, as the API takes them.
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+#[serde(untagged)]
+pub enum Content<T> {
+    Text(T),
+    Parts(Vec<ContentPart<T>>),
+    /// null, in an assistant message that only calls tools
+    Null
+}
+
+impl<T: Default> Default for Content<T> {
+    fn default() -> Self {
+        Content::Text(T::default())
+    }
+}
+
+impl<T: AsRef<str>> Content<T> {
+    /// The string, None for parts or null
+    pub fn as_text(&self) -> Option<&T> {
+        match self {
+            Content::Text(text) => Some(text),
+            _ => None
+        }
+    }
+
+    /// Parts, empty for a string or null
+    pub fn get_parts(&self) -> &[ContentPart<T>] {
+        match self {
+            Content::Parts(parts) => parts,
+            _ => &[]
+        }
+    }
+
+    /// The string, or the text parts one per line, empty for null
+    pub fn to_text(&self) -> String {
+        match self {
+            Content::Text(text) => text.as_ref().to_string(),
+            Content::Parts(parts) => parts.iter().filter_map(ContentPart::as_text).map(AsRef::as_ref).collect::<Vec<&str>>().join("\n"),
+            Content::Null => String::new()
+        }
+    }
+
+    pub fn has_images(&self) -> bool {
+        self.get_parts().iter().any(|p| p.as_image().is_some())
+    }
+
+    pub(crate) fn text_chars(&self) -> usize {
+        match self {
+            Content::Text(text) => text.as_ref().chars().count(),
+            Content::Parts(parts) => parts.iter().filter_map(ContentPart::as_text).map(|t| t.as_ref().chars().count()).sum(),
+            Content::Null => 0
+        }
+    }
+
+    /// Rough cost of the images, the real one depends on their size
+    pub(crate) fn image_tokens(&self) -> u32 {
+        self.get_parts().iter()
+            .filter_map(ContentPart::as_image)
+            .map(|image| match image.detail {
+                Some(Detail::Low) => LOW_DETAIL_TOKENS,
+                _ => HIGH_DETAIL_TOKENS
+            })
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod content_tests {
+    use serde_test::{assert_tokens, Token};
+
+    use super::*;
+
+    const PNG: &[u8] = &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0, 0, 0, 0x0D];
+
+    #[test]
+    fn test_se_de_content() {
+        assert_tokens(&Content::Text("hi"), &[Token::BorrowedStr("hi")]);
+        assert_tokens(&Content::<&str>::Null, &[Token::Unit]);
+        let parts = Content::Parts(vec![
+            ContentPart::text("What is this?"),
+            ContentPart::image_url("https://example.com/cat.png", Some(Detail::Low)).unwrap()
+        ]);
+        assert_tokens(&parts, &[
+            Token::Seq { len: Some(2) },
+            Token::Struct { name: "ContentPart", len: 2 },
+            Token::Str("type"),
+            Token::Str("text"),
+            Token::Str("text"),
+            Token::BorrowedStr("What is this?"),
+            Token::StructEnd,
+            Token::Struct { name: "ContentPart", len: 2 },
+            Token::Str("type"),
+            Token::Str("image_url"),
+            Token::Str("image_url"),
+            Token::Struct { name: "ImageUrl", len: 2 },
+            Token::Str("url"),
+            Token::Str("https://example.com/cat.png"),
+            Token::Str("detail"),
+            Token::Some,
+            Token::UnitVariant { name: "Detail", variant: "low" },
+            Token::StructEnd,
+            Token::StructEnd,
+            Token::SeqEnd
+        ]);
+        assert_eq!(parts.to_text(), "What is this?");
+        assert!(parts.has_images());
+        assert_eq!(parts.image_tokens(), 85);
+        assert!(ContentPart::<&str>::image_url("file:///tmp/cat.png", None).is_err());
+    }
+
+    #[test]
+    fn test_image_bytes() {
+        assert_eq!(image_mime(PNG), Some("image/png"));
+        assert_eq!(image_mime(&[0xFF, 0xD8, 0xFF, 0xE0]), Some("image/jpeg"));
+        assert_eq!(image_mime(b"GIF89a..."), Some("image/gif"));
+        assert_eq!(image_mime(b"RIFF\x24\0\0\0WEBPVP8 "), Some("image/webp"));
+        assert_eq!(image_mime(b"%PDF-1.7"), None);
+
+        let path = std::env::temp_dir().join(format!("xtgptr-image-{}.png", std::process::id()));
+        std::fs::write(&path, PNG).unwrap();
+        let part = ContentPart::<String>::image_file(&path, None).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(part.as_image().unwrap().get_url(), "data:image/png;base64,iVBORw0KGgoAAAAN");
+        assert!(ContentPart::<String>::image_bytes(b"%PDF-1.7", None).is_err());
+        assert!(matches!(ContentPart::<String>::image_file(&path, None), Err(Error::Io(_))));
+
+        let huge = std::env::temp_dir().join(format!("xtgptr-huge-{}.png", std::process::id()));
+        File::create(&huge).unwrap().set_len(MAX_IMAGE_BYTES as u64 + 1).unwrap();
+        let err = ContentPart::<String>::image_file(&huge, None).unwrap_err();
+        std::fs::remove_file(&huge).unwrap();
+        assert!(matches!(err, Error::Validation { field: "image_url", .. }), "{:?}", err);
+    }
+}
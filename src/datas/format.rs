@@ -0,0 +1,126 @@
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+
+use super::tool::check_function_name;
+use crate::error::Error;
+use crate::schema::JsonSchema;
+
+/// Schema the answer must follow, see [`ResponseFormat::JsonSchema`]
+#[skip_serializing_none]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct JsonSchemaFormat {
+    /// a-z, A-Z, 0-9, underscores and dashes, at most 64 characters
+    name: String,
+    description: Option<String>,
+    schema: Value,
+    /// Make the model follow `schema` exactly (structured outputs)
+    strict: Option<bool>
+}
+
+impl JsonSchemaFormat {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn get_schema(&self) -> &Value {
+        &self.schema
+    }
+
+    pub fn get_strict(&self) -> Option<bool> {
+        self.strict
+    }
+}
+
+/// Shape of the answer
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ResponseFormat {
+    /// Free text, the default
+    Text,
+    /// Any valid JSON object, the prompt must ask for JSON
+    JsonObject,
+    /// JSON following a schema
+    JsonSchema { json_schema: JsonSchemaFormat }
+}
+
+impl ResponseFormat {
+    /// `schema` must be a JSON schema object
+    pub fn json_schema(name: impl Into<String>, schema: Value, strict: bool) -> Result<ResponseFormat, Error> {
+        let name = name.into();
+        check_function_name(&name).map_err(|_| Error::validation("response_format", format!("name {:?} must be 1 to 64 of a-z, A-Z, 0-9, _ and -", name)))?;
+        if !schema.is_object() {
+            return Err(Error::validation("response_format", format!("schema of {} must be a JSON schema object", name)));
+        }
+        Ok(ResponseFormat::JsonSchema { json_schema: JsonSchemaFormat { name, description: None, schema, strict: Some(strict) } })
+    }
+
+    /// Strict schema of `T`, named after it \
+    /// The API wants an object at the top, so `T` must be a struct (wrap enums in one).
+    pub fn typed<T: JsonSchema>() -> Result<ResponseFormat, Error> {
+        let schema = T::schema();
+        if schema.get("type") != Some(&Value::from("object")) {
+            return Err(Error::validation("response_format", format!("schema of {} must describe an object", T::schema_name())));
+        }
+        ResponseFormat::json_schema(T::schema_name(), schema, true)
+    }
+
+    pub fn set_description(&mut self, description: impl Into<String>) {
+        if let ResponseFormat::JsonSchema { json_schema } = self {
+            json_schema.description = Some(description.into());
+        }
+    }
+
+    /// Whether the answer is JSON
+    pub fn is_json(&self) -> bool {
+        !matches!(self, ResponseFormat::Text)
+    }
+}
+
+#[cfg(test)]
+mod format_tests {
+    use serde_json::json;
+    use serde_test::{assert_tokens, Token};
+
+    use super::*;
+
+    #[derive(Deserialize, JsonSchema)]
+    struct Person {
+        name: String
+    }
+
+    #[test]
+    fn test_se_de_response_format() {
+        assert_tokens(&ResponseFormat::JsonObject, &[
+            Token::Struct { name: "ResponseFormat", len: 1 },
+            Token::Str("type"),
+            Token::Str("json_object"),
+            Token::StructEnd
+        ]);
+        let mut format = ResponseFormat::typed::<Person>().unwrap();
+        format.set_description("Someone");
+        assert_eq!(serde_json::to_value(&format).unwrap(), json!({
+            "type": "json_schema",
+            "json_schema": {
+                "name": "Person",
+                "description": "Someone",
+                "strict": true,
+                "schema": {
+                    "type": "object",
+                    "properties": { "name": { "type": "string" } },
+                    "required": ["name"],
+                    "additionalProperties": false
+                }
+            }
+        }));
+        assert!(format.is_json());
+        assert_eq!(crate::schema::from_str::<Person>("{\"name\":\"Ada\"}").unwrap().name, "Ada");
+        assert!(ResponseFormat::typed::<Vec<String>>().is_err());
+        assert!(ResponseFormat::json_schema("a b", json!({}), false).is_err());
+    }
+}
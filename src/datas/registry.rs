@@ -0,0 +1,280 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Add;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::Error;
+
+/// Data of the built-in registry, same format as [`Registry::from_json`]
+const BUILTIN: &str = include_str!("models.json");
+
+/// Features a model may support
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(default)]
+pub struct Features {
+    /// Function / tool calling
+    pub tools: bool,
+    /// Images in user messages
+    pub vision: bool,
+    /// `response_format` of type json_object
+    pub json_mode: bool
+}
+
+/// What a model can take and give, and what it costs
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Debug)]
+pub struct ModelInfo {
+    /// Prompt and answer tokens together
+    context_window: u32,
+    max_output_tokens: u32,
+    /// USD per 1K prompt tokens
+    input_per_1k: Option<f64>,
+    /// USD per 1K answer tokens
+    output_per_1k: Option<f64>,
+    /// USD per 1K prompt tokens served from the prompt cache, `input_per_1k` when unset
+    cached_input_per_1k: Option<f64>,
+    #[serde(default)]
+    features: Features
+}
+
+impl ModelInfo {
+    pub fn new(context_window: u32, max_output_tokens: u32, features: Features) -> ModelInfo {
+        ModelInfo { context_window, max_output_tokens, input_per_1k: None, output_per_1k: None, cached_input_per_1k: None, features }
+    }
+
+    /// Prices in USD per 1K tokens
+    pub fn set_prices(&mut self, input_per_1k: f64, output_per_1k: f64, cached_input_per_1k: Option<f64>) {
+        self.input_per_1k = Some(input_per_1k);
+        self.output_per_1k = Some(output_per_1k);
+        self.cached_input_per_1k = cached_input_per_1k;
+    }
+
+    pub fn get_context_window(&self) -> u32 {
+        self.context_window
+    }
+
+    pub fn get_max_output_tokens(&self) -> u32 {
+        self.max_output_tokens
+    }
+
+    pub fn get_input_per_1k(&self) -> Option<f64> {
+        self.input_per_1k
+    }
+
+    pub fn get_output_per_1k(&self) -> Option<f64> {
+        self.output_per_1k
+    }
+
+    pub fn get_cached_input_per_1k(&self) -> Option<f64> {
+        self.cached_input_per_1k.or(self.input_per_1k)
+    }
+
+    pub fn get_features(&self) -> Features {
+        self.features
+    }
+
+    /// Price of a request, None if the model has no prices \
+    /// `cached_tokens` are part of `prompt_tokens`, as reported in `Usage`
+    pub fn cost(&self, prompt_tokens: u64, cached_tokens: u64, completion_tokens: u64) -> Option<Cost> {
+        let cached_tokens = cached_tokens.min(prompt_tokens);
+        Some(Cost {
+            input: (prompt_tokens - cached_tokens) as f64 / 1000.0 * self.input_per_1k?,
+            cached_input: cached_tokens as f64 / 1000.0 * self.get_cached_input_per_1k()?,
+            output: completion_tokens as f64 / 1000.0 * self.output_per_1k?
+        })
+    }
+}
+
+/// Price of a request in USD
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Cost {
+    input: f64,
+    cached_input: f64,
+    output: f64
+}
+
+impl Cost {
+    pub fn get_input(&self) -> f64 {
+        self.input
+    }
+
+    pub fn get_cached_input(&self) -> f64 {
+        self.cached_input
+    }
+
+    pub fn get_output(&self) -> f64 {
+        self.output
+    }
+
+    pub fn total(&self) -> f64 {
+        self.input + self.cached_input + self.output
+    }
+}
+
+impl Add for Cost {
+    type Output = Cost;
+
+    fn add(self, other: Cost) -> Cost {
+        Cost { input: self.input + other.input, cached_input: self.cached_input + other.cached_input, output: self.output + other.output }
+    }
+}
+
+impl fmt::Display for Cost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${:.6}", self.total())
+    }
+}
+
+#[derive(Deserialize, Serialize, Default, Debug)]
+struct RegistryFile {
+    #[serde(default)]
+    models: HashMap<String, ModelInfo>
+}
+
+/// Limits, prices and features by model name \
+/// [`builtin`](Registry::builtin) is embedded in the crate, override or extend it from a file:
+/// ```toml
+/// [models."my-finetune"]
+/// context_window = 16385
+/// max_output_tokens = 4096
+/// input_per_1k = 0.003
+/// output_per_1k = 0.006
+/// features = { tools = true }
+/// ```
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Registry {
+    models: HashMap<String, ModelInfo>
+}
+
+impl Registry {
+    /// Registry embedded in the crate
+    pub fn builtin() -> &'static Registry {
+        static BUILTIN_REGISTRY: OnceLock<Registry> = OnceLock::new();
+        BUILTIN_REGISTRY.get_or_init(|| Registry::from_json(BUILTIN).expect("embedded models.json is valid"))
+    }
+
+    /// `{"models": {"<name>": {"context_window": .., "max_output_tokens": .., ...}}}`
+    pub fn from_json(text: &str) -> Result<Registry, Error> {
+        let file: RegistryFile = serde_json::from_str(text)?;
+        Ok(Registry { models: file.models })
+    }
+
+    /// Same layout as [`from_json`](Registry::from_json), as TOML tables
+    pub fn from_toml(text: &str) -> Result<Registry, Error> {
+        let file: RegistryFile = toml::from_str(text).map_err(|e| Error::Decode(e.to_string()))?;
+        Ok(Registry { models: file.models })
+    }
+
+    /// Read a `.toml` file, anything else is read as JSON
+    pub fn load(path: impl AsRef<Path>) -> Result<Registry, Error> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => Registry::from_toml(&text),
+            _ => Registry::from_json(&text)
+        }
+    }
+
+    /// The built-in registry with the models of `path` added or replaced
+    pub fn builtin_with(path: impl AsRef<Path>) -> Result<Registry, Error> {
+        let mut registry = Registry::builtin().clone();
+        registry.merge(Registry::load(path)?);
+        Ok(registry)
+    }
+
+    /// Add the models of `other`, replacing those with the same name
+    pub fn merge(&mut self, other: Registry) {
+        self.models.extend(other.models);
+    }
+
+    pub fn insert(&mut self, name: impl Into<String>, info: ModelInfo) {
+        self.models.insert(name.into(), info);
+    }
+
+    /// Info of `name`, or of the registered name it is a dated snapshot of ("gpt-4o-2024-08-06" gets "gpt-4o") \
+    /// Other suffixes name other models, "gpt-4-32k" or "o1-preview" get None rather than the info of "gpt-4" or "o1".
+    pub fn get(&self, name: &str) -> Option<&ModelInfo> {
+        if let Some(info) = self.models.get(name) {
+            return Some(info);
+        }
+        self.models.iter()
+            .find(|(known, _)| name.strip_prefix(known.as_str()).map(is_snapshot_date).unwrap_or(false))
+            .map(|(_, info)| info)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.models.keys()
+    }
+
+    /// Price of a request to `name`, None if the model or its prices are unknown
+    pub fn cost(&self, name: &str, prompt_tokens: u64, cached_tokens: u64, completion_tokens: u64) -> Option<Cost> {
+        self.get(name)?.cost(prompt_tokens, cached_tokens, completion_tokens)
+    }
+}
+
+/// `-YYYY-MM-DD` or `-MMDD`, the dates ending the names of snapshots
+fn is_snapshot_date(suffix: &str) -> bool {
+    let digits = |part: &str, len: usize| part.len() == len && part.bytes().all(|b| b.is_ascii_digit());
+    let Some(date) = suffix.strip_prefix('-') else { return false };
+    let parts: Vec<&str> = date.split('-').collect();
+    match parts[..] {
+        [mmdd] => digits(mmdd, 4),
+        [year, month, day] => digits(year, 4) && digits(month, 2) && digits(day, 2),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod registry_tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin() {
+        let registry = Registry::builtin();
+        let info = registry.get("gpt-4o").unwrap();
+        assert_eq!(info.get_context_window(), 128_000);
+        assert!(info.get_features().vision);
+        assert_eq!(registry.get("gpt-4o-2024-08-06"), Some(info));
+        assert_eq!(registry.get("gpt-4o-mini-2024-07-18").unwrap().get_input_per_1k(), Some(0.00015));
+        assert_eq!(registry.get("gpt-4ox"), None);
+        assert_eq!(registry.get("gpt-4-0613"), registry.get("gpt-4"));
+        assert_eq!(registry.get("gpt-4-32k"), None);
+        assert_eq!(registry.get("o1-preview"), None);
+        assert_eq!(registry.get("gpt-4o-audio-preview"), None);
+        assert_eq!(registry.get("gpt-4o-audio-preview-2024-12-17"), None);
+        assert_eq!(registry.get("llama"), None);
+    }
+
+    #[test]
+    fn test_overrides() {
+        let path = std::env::temp_dir().join(format!("xtgptr-registry-{}.toml", std::process::id()));
+        std::fs::write(&path, "[models.\"gpt-4o\"]\ncontext_window = 64000\nmax_output_tokens = 4096\n\n[models.local]\ncontext_window = 8192\nmax_output_tokens = 2048\nfeatures = { tools = true }\n").unwrap();
+        let registry = Registry::builtin_with(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(registry.get("gpt-4o").unwrap().get_context_window(), 64_000);
+        assert_eq!(registry.get("gpt-4o").unwrap().get_input_per_1k(), None);
+        assert!(registry.get("local").unwrap().get_features().tools);
+        assert!(!registry.get("local").unwrap().get_features().vision);
+        assert!(registry.get("o3").is_some());
+        assert!(Registry::from_json("{\"models\": {\"x\": {\"context_window\": \"big\"}}}").is_err());
+    }
+
+    #[test]
+    fn test_cost() {
+        let registry = Registry::builtin();
+        let cost = registry.cost("gpt-4o", 2000, 1000, 500).unwrap();
+        assert!((cost.get_input() - 0.0025).abs() < 1e-12);
+        assert!((cost.get_cached_input() - 0.00125).abs() < 1e-12);
+        assert!((cost.get_output() - 0.005).abs() < 1e-12);
+        assert_eq!(cost.to_string(), "$0.008750");
+        // no cache price: cached tokens cost as much as the others
+        assert!((registry.cost("gpt-4", 1000, 500, 0).unwrap().total() - 0.03).abs() < 1e-12);
+        let mut custom = Registry::default();
+        custom.insert("free", ModelInfo::new(4096, 4096, Features::default()));
+        assert_eq!(custom.cost("free", 1, 0, 1), None);
+        assert_eq!(custom.cost("unknown", 1, 0, 1), None);
+    }
+}
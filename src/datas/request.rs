@@ -1,22 +1,37 @@
 use serde_with::skip_serializing_none;
 use std::collections::HashMap;
+use std::fmt;
 use std::str;
-use std::convert::From;
-use serde::{Serialize, Deserialize};
-use reqwest::header::{HeaderValue, InvalidHeaderValue};
-
-use super::{AUTH_METHOD, MAX_N};
-
-/**
+use std::sync::Arc;
+use serde::{Serialize, Serializer, Deserialize, Deserializer};
+use reqwest::header::HeaderValue;
+
+use super::AUTH_METHOD;
+use super::MAX_N;
+use super::builder::BodyBuilder;
+use super::content::Content;
+use super::content::ContentPart;
+use super::format::ResponseFormat;
+use super::registry::ModelInfo;
+use super::registry::Registry;
+use super::tool::MAX_TOOLS;
+use super::tool::Tool;
+use super::tool::ToolCall;
+use super::tool::ToolChoice;
+use super::tool::check_function_name;
+use crate::error::Error;
+use crate::secret::Secret;
+
+/*
  * ======
  * BASIC DATA STRUCTS
  * ======
  */
 
 /// Not used in response
-#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
 #[serde(untagged)]
-enum StringOrArray<T> {
+pub enum StringOrArray<T> {
     Str(T),
     Arr(Vec<T>)
 }
@@ -31,93 +46,246 @@ impl<T: AsRef<str>> PartialEq<StringOrArray<T>> for &StringOrArray<T> {
     }
 }
 
-/**
+/*
  * ======
  * REQUEST BODY DATA
  * ======
  */
 
-#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+/// Model answering the request \
+/// Serialized as its plain name. Names without a variant (newer models, fine-tunes, local models)
+/// go through `Custom`, so any name coming back in a response parses.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Models {
-    /// GPT-3.5-Turbo
-    #[serde(rename = "gpt-3.5-turbo")]
+    /// gpt-3.5-turbo
     GPT35Turbo,
-    /// GPT-3.5-Turbo-0301
-    #[serde(rename = "gpt-3.5-turbo-0301")]
-    GPT35Turbo0301
+    /// gpt-3.5-turbo-0301
+    GPT35Turbo0301,
+    /// gpt-3.5-turbo-0125
+    GPT35Turbo0125,
+    /// gpt-4
+    GPT4,
+    /// gpt-4-turbo
+    GPT4Turbo,
+    /// gpt-4o
+    GPT4o,
+    /// gpt-4o-mini
+    GPT4oMini,
+    /// gpt-4.1
+    GPT41,
+    /// gpt-4.1-mini
+    GPT41Mini,
+    /// gpt-4.1-nano
+    GPT41Nano,
+    /// o1
+    O1,
+    /// o1-mini
+    O1Mini,
+    /// o3
+    O3,
+    /// o3-mini
+    O3Mini,
+    /// o4-mini
+    O4Mini,
+    /// Any other model name, sent as is
+    Custom(String)
+}
+
+impl Models {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Models::GPT35Turbo => "gpt-3.5-turbo",
+            Models::GPT35Turbo0301 => "gpt-3.5-turbo-0301",
+            Models::GPT35Turbo0125 => "gpt-3.5-turbo-0125",
+            Models::GPT4 => "gpt-4",
+            Models::GPT4Turbo => "gpt-4-turbo",
+            Models::GPT4o => "gpt-4o",
+            Models::GPT4oMini => "gpt-4o-mini",
+            Models::GPT41 => "gpt-4.1",
+            Models::GPT41Mini => "gpt-4.1-mini",
+            Models::GPT41Nano => "gpt-4.1-nano",
+            Models::O1 => "o1",
+            Models::O1Mini => "o1-mini",
+            Models::O3 => "o3",
+            Models::O3Mini => "o3-mini",
+            Models::O4Mini => "o4-mini",
+            Models::Custom(name) => name
+        }
+    }
+
+    /// Limits, prices and features from the built-in [`Registry`], None for names it doesn't know
+    pub fn info(&self) -> Option<ModelInfo> {
+        Registry::builtin().get(self.as_str()).copied()
+    }
+}
+
+impl From<&str> for Models {
+    fn from(name: &str) -> Self {
+        match name {
+            "gpt-3.5-turbo" => Models::GPT35Turbo,
+            "gpt-3.5-turbo-0301" => Models::GPT35Turbo0301,
+            "gpt-3.5-turbo-0125" => Models::GPT35Turbo0125,
+            "gpt-4" => Models::GPT4,
+            "gpt-4-turbo" => Models::GPT4Turbo,
+            "gpt-4o" => Models::GPT4o,
+            "gpt-4o-mini" => Models::GPT4oMini,
+            "gpt-4.1" => Models::GPT41,
+            "gpt-4.1-mini" => Models::GPT41Mini,
+            "gpt-4.1-nano" => Models::GPT41Nano,
+            "o1" => Models::O1,
+            "o1-mini" => Models::O1Mini,
+            "o3" => Models::O3,
+            "o3-mini" => Models::O3Mini,
+            "o4-mini" => Models::O4Mini,
+            other => Models::Custom(other.to_string())
+        }
+    }
+}
+
+impl From<String> for Models {
+    fn from(name: String) -> Self {
+        match Models::from(name.as_str()) {
+            Models::Custom(_) => Models::Custom(name),
+            known => known
+        }
+    }
+}
+
+impl fmt::Display for Models {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for Models {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for Models {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Models::from)
+    }
 }
 
 impl PartialEq<Models> for &Models {
     fn eq(&self, other: &Models) -> bool {
-        matches!((self, other), (Models::GPT35Turbo, Models::GPT35Turbo) | (Models::GPT35Turbo0301, Models::GPT35Turbo0301))
+        **self == *other
     }
 }
 
-#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
 #[serde(rename_all = "lowercase")]
 pub enum Roles {
     System,
     User,
-    Assistant
+    Assistant,
+    /// Result of a tool call
+    Tool
 }
 
 impl PartialEq<Roles> for &Roles {
     fn eq(&self, other: &Roles) -> bool {
-        matches!((self, other), (Roles::System, Roles::System) | (Roles::User, Roles::User) | (Roles::Assistant, Roles::Assistant))
+        **self == *other
     }
 }
 
+/// Validator of [`KeyPolicy::Custom`]
+pub type KeyCheck = Arc<dyn Fn(&str) -> Result<(), String> + Send + Sync>;
+
+/// Which keys [`ChatLogin`] accepts
+#[derive(Clone, Default)]
+pub enum KeyPolicy {
+    /// OpenAI style keys: "sk-" then at least 32 of [a-zA-Z0-9_-], covers legacy, project ("sk-proj-...") and service account keys
+    #[default]
+    Strict,
+    /// Any non-empty key without whitespace, for compatible servers with their own key format
+    Permissive,
+    /// Validate with your own function, its error message is reported as the reason
+    Custom(KeyCheck)
+}
+
+impl KeyPolicy {
+    pub fn custom(check: impl Fn(&str) -> Result<(), String> + Send + Sync + 'static) -> KeyPolicy {
+        KeyPolicy::Custom(Arc::new(check))
+    }
+
+    /// Check a key, given without the "Bearer " prefix
+    pub fn check(&self, key: &str) -> Result<(), Error> {
+        if key.is_empty() || key.chars().any(char::is_whitespace) {
+            return Err(Error::validation("auth", "Auth format illegal"));
+        }
+        match self {
+            KeyPolicy::Strict => match key.strip_prefix("sk-") {
+                Some(rest) if rest.len() >= 32 && rest.chars().all(|x| x.is_ascii_alphanumeric() || x == '-' || x == '_') => Ok(()),
+                _ => Err(Error::validation("auth", "Auth format illegal"))
+            },
+            KeyPolicy::Permissive => Ok(()),
+            KeyPolicy::Custom(check) => check(key).map_err(|reason| Error::validation("auth", reason))
+        }
+    }
+}
+
+impl fmt::Debug for KeyPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyPolicy::Strict => write!(f, "Strict"),
+            KeyPolicy::Permissive => write!(f, "Permissive"),
+            KeyPolicy::Custom(_) => write!(f, "Custom")
+        }
+    }
+}
+
+#[derive(Debug)]
 pub struct ChatLogin<S> {
-    /// Bearer <OpenAI_Key> \
-    /// Its OpenAI_Key that should be filled in by users \
-    /// Just store Str, use HeaderValue to check
-    auth: S,
-    organization: Option<S>
+    /// <OpenAI_Key>, stored without the "Bearer " prefix \
+    /// Its OpenAI_Key that should be filled in by users, kept as a [`Secret`] so it never shows in logs
+    auth: Secret,
+    organization: Option<S>,
+    policy: KeyPolicy
 }
 
 impl<S: AsRef<str>> ChatLogin<S> {
-    /// auth must be "Bearer sk-[a-zA-Z0-9]{48}"
-    pub fn new(auth: S, organization: Option<S>) -> Result<ChatLogin<S>, String> {
-        let collects: Vec<&str> = auth.as_ref().split(' ').collect();
-        if collects.len() != 2 { return Err(format!{"Auth format illegal"}); };
-        let mut gets: Vec<&str> = collects[1].split('-').collect();
-        gets.push(collects[0]);
-        if gets[0].ne("sk") || (gets[1].len() != 48 || !gets[1].chars().all(|x| x.is_ascii_alphanumeric())) || gets[2].ne("Bearer") { 
-            return Err(format!{"Auth format illegal"}); 
-        };
-        if let Err(_) = HeaderValue::from_str(auth.as_ref()) {
-            return Err(format!("Auth is not legal header value"));
-        };
+    /// auth must pass [`KeyPolicy::Strict`], use [`with_policy`](ChatLogin::with_policy) for other keys \
+    /// It may be given with or without the "Bearer " prefix
+    pub fn new(auth: S, organization: Option<S>) -> Result<ChatLogin<S>, Error> {
+        ChatLogin::with_policy(auth, organization, KeyPolicy::Strict)
+    }
+
+    pub fn with_policy(auth: S, organization: Option<S>, policy: KeyPolicy) -> Result<ChatLogin<S>, Error> {
+        ChatLogin::from_secret(&Secret::new(auth.as_ref()), organization, policy)
+    }
+
+    /// Like [`with_policy`](ChatLogin::with_policy), for a key already held as a [`Secret`]
+    pub fn from_secret(auth: &Secret, organization: Option<S>, policy: KeyPolicy) -> Result<ChatLogin<S>, Error> {
+        let auth = check_auth(auth.expose(), &policy)?;
         if let Some(Err(_)) = organization.as_ref().map(|org| HeaderValue::from_str(org.as_ref())) {
-            return Err(format!("Invalid Organization format"))
+            return Err(Error::validation("organization", "Invalid Organization format"))
         };
-        Ok(ChatLogin::<S>{auth, organization})
+        Ok(ChatLogin::<S>{auth, organization, policy})
     }
 
-    pub fn set_auth(&mut self, auth: S) -> Result<(), String> {
-        let mut collects: Vec<&str> = auth.as_ref().split(' ').collect();
-        if collects.len() != 2 { return Err(format!{"Auth format illegal"}); };
-        let mut gets: Vec<&str> = collects[1].split('-').collect();
-        gets.push(collects[0]);
-        if gets[0].ne("sk") || (gets[1].len() != 48 || !gets[1].chars().all(|x| x.is_ascii_alphanumeric())) || gets[2].ne("Bearer") { 
-            return Err(format!{"Auth format illegal"}); 
-        };
-        if let Err(_) = HeaderValue::from_str(auth.as_ref()) {
-            return Err(format!("Auth is not legal header value"));
-        };
-        self.auth = auth;
+    pub fn set_auth(&mut self, auth: S) -> Result<(), Error> {
+        self.auth = check_auth(auth.as_ref(), &self.policy)?;
         Ok(())
     }
 
-    pub fn set_organization(&mut self, organization: S) -> Result<(), String> {
-        if let Err(_) = HeaderValue::from_str(organization.as_ref()) {
-            return Err(format!("Invalid Organization format"))
+    pub fn set_organization(&mut self, organization: S) -> Result<(), Error> {
+        if HeaderValue::from_str(organization.as_ref()).is_err() {
+            return Err(Error::validation("organization", "Invalid Organization format"))
         };
         self.organization = Some(organization);
         Ok(())
     }
 
-    pub fn get_auth(&self) -> &S {
+    /// Applies to the next [`set_auth`](ChatLogin::set_auth), the current key is kept
+    pub fn set_policy(&mut self, policy: KeyPolicy) {
+        self.policy = policy;
+    }
+
+    /// The key, without the "Bearer " prefix
+    pub fn get_auth(&self) -> &Secret {
         &self.auth
     }
 
@@ -125,6 +293,10 @@ impl<S: AsRef<str>> ChatLogin<S> {
         self.organization.as_ref()
     }
 
+    pub fn get_policy(&self) -> &KeyPolicy {
+        &self.policy
+    }
+
     // /// check if given token can access to api
     // async fn check_access(&self) -> bool {
     //     let client = Client::new();
@@ -143,15 +315,91 @@ impl<S: AsRef<str>> ChatLogin<S> {
     // }
 }
 
-#[derive(Deserialize, Serialize, PartialEq, Eq, Debug)]
+/// Key of `auth` once it passed `policy`
+fn check_auth(auth: &str, policy: &KeyPolicy) -> Result<Secret, Error> {
+    let key = auth.strip_prefix(AUTH_METHOD).and_then(|rest| rest.strip_prefix(' ')).unwrap_or(auth);
+    policy.check(key)?;
+    let key = Secret::new(key);
+    key.header_value("")?;
+    Ok(key)
+}
+
+/// Credentials of an Azure OpenAI resource, sent in the `api-key` header \
+/// Pair it with [`Config::azure`](crate::netreq::config::Config::azure) to route to a deployment
+#[derive(Debug)]
+pub struct AzureLogin {
+    /// Key of the resource, as shown in the Azure portal (no "Bearer " prefix)
+    api_key: Secret
+}
+
+impl AzureLogin {
+    pub fn new(api_key: impl AsRef<str>) -> Result<AzureLogin, Error> {
+        Ok(AzureLogin { api_key: check_azure_key(api_key.as_ref())? })
+    }
+
+    pub fn set_api_key(&mut self, api_key: impl AsRef<str>) -> Result<(), Error> {
+        self.api_key = check_azure_key(api_key.as_ref())?;
+        Ok(())
+    }
+
+    pub fn get_api_key(&self) -> &Secret {
+        &self.api_key
+    }
+}
+
+fn check_azure_key(api_key: &str) -> Result<Secret, Error> {
+    if api_key.trim().is_empty() {
+        return Err(Error::validation("api_key", "api_key is empty"));
+    };
+    let api_key = Secret::new(api_key);
+    api_key.header_value("").map_err(|_| Error::validation("api_key", "api_key is not legal header value"))?;
+    Ok(api_key)
+}
+
+#[skip_serializing_none]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
 pub struct Message<T> {
     role: Roles,
-    content: T
+    /// A string, or text and image parts \
+    /// Null when an assistant message only calls tools
+    content: Content<T>,
+    /// Name of the participant, to tell apart several users or assistants
+    name: Option<String>,
+    /// Calls requested by an assistant message
+    tool_calls: Option<Vec<ToolCall>>,
+    /// Call a `Tool` message answers
+    tool_call_id: Option<String>,
+    /// Why the model declined to answer, set instead of the content
+    refusal: Option<String>
 }
 
 impl<T: AsRef<str>> Message<T> {
     pub fn new(role: Roles, content: T) -> Message<T> {
-        Message{role, content}
+        Message{role, content: Content::Text(content), name: None, tool_calls: None, tool_call_id: None, refusal: None}
+    }
+
+    /// Message made of text and images, images are only taken in user messages
+    pub fn parts(role: Roles, parts: Vec<ContentPart<T>>) -> Message<T> {
+        Message{role, content: Content::Parts(parts), name: None, tool_calls: None, tool_call_id: None, refusal: None}
+    }
+
+    /// Answer to the tool call `tool_call_id`
+    pub fn tool(tool_call_id: impl Into<String>, content: T) -> Message<T> {
+        Message { tool_call_id: Some(tool_call_id.into()), ..Message::new(Roles::Tool, content) }
+    }
+
+    /// Assistant message calling tools, to put back in the history before their answers \
+    /// Its content is null when None, as the API sends it for a message that only calls tools.
+    pub fn tool_calls(content: Option<T>, tool_calls: Vec<ToolCall>) -> Message<T> {
+        let content = content.map(Content::Text).unwrap_or(Content::Null);
+        Message { role: Roles::Assistant, content, name: None, tool_calls: Some(tool_calls), tool_call_id: None, refusal: None }
+    }
+
+    pub fn set_name(&mut self, name: impl Into<String>) -> E {
+        let name = name.into();
+        check_function_name(&name).map_err(|_| Error::validation("name", format!("name {:?} must be 1 to 64 of a-z, A-Z, 0-9, _ and -", name)))?;
+        self.name = Some(name);
+        Ok(())
     }
 
     pub fn set_role(&mut self, role: Roles) {
@@ -159,22 +407,44 @@ impl<T: AsRef<str>> Message<T> {
     }
 
     pub fn set_content(&mut self, content: T) {
-        self.content = content;
+        self.content = Content::Text(content);
+    }
+
+    pub fn set_parts(&mut self, parts: Vec<ContentPart<T>>) {
+        self.content = Content::Parts(parts);
     }
 
     pub fn get_role(&self) -> &Roles {
         &self.role
     }
 
-    pub fn get_content(&self) -> &T {
+    pub fn get_content(&self) -> &Content<T> {
         &self.content
     }
+
+    pub fn get_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Calls requested by an assistant message, empty if none
+    pub fn get_tool_calls(&self) -> &[ToolCall] {
+        self.tool_calls.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_tool_call_id(&self) -> Option<&str> {
+        self.tool_call_id.as_deref()
+    }
+
+    pub fn get_refusal(&self) -> Option<&str> {
+        self.refusal.as_deref()
+    }
 }
 
 /// request body
 /// * note: All Introductions are from OpenAI official website, copyright by OpenAI
 #[skip_serializing_none]
-#[derive(Deserialize, Serialize, PartialEq, Debug)]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+#[serde(bound(deserialize = "Sentence: Deserialize<'de> + Default"))]
 pub struct Body<Sentence> {
     model: Models,
     messages: Vec<Message<Sentence>>,
@@ -221,16 +491,27 @@ pub struct Body<Sentence> {
     logit_bias: Option<HashMap<u32, i32>>,
     /// A unique identifier representing your end-user, which can help OpenAI to monitor and detect abuse. Learn more. \
     /// no default, thus take null as default
-    user: Option<Sentence>
+    user: Option<Sentence>,
+    /// Functions the model may call, at most 128
+    tools: Option<Vec<Tool>>,
+    /// Whether and which tool the model must call \
+    /// default to auto when there are tools, none otherwise
+    tool_choice: Option<ToolChoice>,
+    /// Whether the model may call several tools in one answer \
+    /// default to true
+    parallel_tool_calls: Option<bool>,
+    /// Free text, any JSON object or JSON following a schema \
+    /// default to text
+    response_format: Option<ResponseFormat>
 }
 
 impl<Sentence: AsRef<str>> Default for Body<Sentence> {
     fn default() -> Self {
-        Body { model: Models::GPT35Turbo, messages: Vec::<Message<Sentence>>::new(), temperature: None, top_p: None, n: None, stream: None, stop: None, max_tokens: None, presence_penalty: None, frequency_penalty: None, logit_bias: None, user: None }
+        Body { model: Models::GPT35Turbo, messages: Vec::<Message<Sentence>>::new(), temperature: None, top_p: None, n: None, stream: None, stop: None, max_tokens: None, presence_penalty: None, frequency_penalty: None, logit_bias: None, user: None, tools: None, tool_choice: None, parallel_tool_calls: None, response_format: None }
     }
 }
 
-type E = Result<(), String>;
+type E = Result<(), Error>;
 
 impl<Sentence: AsRef<str>> Body<Sentence> {
     pub fn new(model: Models) -> Body<Sentence> {
@@ -238,6 +519,11 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
         Body { model, ..default }
     }
 
+    /// Fluent builder checking every parameter at once, see [`BodyBuilder`]
+    pub fn builder(model: Models) -> BodyBuilder<Sentence> {
+        BodyBuilder::new(model)
+    }
+
     pub fn set_models(&mut self, model: Models) {
         self.model = model;
     }
@@ -248,7 +534,7 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
                 self.temperature = Some(temperature);
                 Ok(())
             },
-            false => Err(String::from("temperature must be between 0 and 2"))
+            false => Err(Error::validation("temperature", "temperature must be between 0 and 2"))
         }
     }
 
@@ -258,7 +544,7 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
                 self.top_p = Some(top_p);
                 Ok(())
             },
-            false => Err(String::from("top_p must be between 0 and 1"))
+            false => Err(Error::validation("top_p", "top_p must be between 0 and 1"))
         }
     }
 
@@ -268,7 +554,7 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
                 self.n = Some(n);
                 Ok(())
             },
-            false => Err(format!("n must be between 0 and {}", MAX_N))
+            false => Err(Error::validation("n", format!("n must be between 1 and {}", MAX_N)))
         }
     }
 
@@ -280,7 +566,7 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
     pub fn set_stop(&mut self, stop: StringOrArray<Sentence>) -> E {
         if let StringOrArray::Arr(soa) = &stop {
             match soa.len() > 4 {
-                true => Err(String::from("stop can't have more than 4 elements")),
+                true => Err(Error::validation("stop", "stop can't have more than 4 elements")),
                 false => {
                     self.stop = Some(stop);
                     Ok(())
@@ -294,7 +580,7 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
 
     pub fn set_max_tokens(&mut self, max_tokens: u32) -> E {
         match max_tokens < 1 {
-            true => Err(String::from("max_tokens must be greater than 0")),
+            true => Err(Error::validation("max_tokens", "max_tokens must be greater than 0")),
             false => {
                 self.max_tokens = Some(max_tokens);
                 Ok(())
@@ -308,7 +594,7 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
                 self.presence_penalty = Some(presence_penalty);
                 Ok(())
             },
-            false => Err(String::from("presence_penalty must be between -2 and 2"))
+            false => Err(Error::validation("presence_penalty", "presence_penalty must be between -2 and 2"))
         }
     }
 
@@ -318,14 +604,14 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
                 self.frequency_penalty = Some(frequency_penalty);
                 Ok(())
             },
-            false => Err(String::from("frequency_penalty must be between -2 and 2"))
+            false => Err(Error::validation("frequency_penalty", "frequency_penalty must be between -2 and 2"))
         }
     }
 
     pub fn set_logit_bias(&mut self, logit_bias: HashMap<u32, i32>) -> E {
         for v in logit_bias.values() {
-            if let false = (-100..=100).contains(v) {
-                return Err(String::from("logit_bias right param must be between -100 and 100"));
+            if !(-100..=100).contains(v) {
+                return Err(Error::validation("logit_bias", "logit_bias right param must be between -100 and 100"));
             };
         }
         self.logit_bias = Some(logit_bias);
@@ -333,8 +619,8 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
     }
 
     pub fn add_logit_bias(&mut self, token: u32, bias: i32) -> E {
-        if let false = (-100..=100).contains(&bias) {
-            return Err(String::from("logit_bias right param must be between -100 and 100"));
+        if !(-100..=100).contains(&bias) {
+            return Err(Error::validation("logit_bias", "logit_bias right param must be between -100 and 100"));
         };
         match &mut self.logit_bias {
             Some(lb) => {
@@ -352,8 +638,8 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
 
     pub fn add_logit_biass(&mut self, logit_biass: HashMap<u32, i32>) -> E {
         for v in logit_biass.values() {
-            if let false = (-100..=100).contains(v) {
-                return Err(String::from("logit_bias right param must be between -100 and 100"));
+            if !(-100..=100).contains(v) {
+                return Err(Error::validation("logit_bias", "logit_bias right param must be between -100 and 100"));
             };
         }
         match &mut self.logit_bias {
@@ -372,6 +658,43 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
         self.user = Some(user);
     }
 
+    /// Declare a tool, its name must not be taken
+    pub fn add_tool(&mut self, tool: Tool) -> E {
+        let tools = self.tools.get_or_insert_with(Vec::new);
+        if tools.len() >= MAX_TOOLS {
+            return Err(Error::validation("tools", format!("at most {} tools", MAX_TOOLS)));
+        }
+        if tools.iter().any(|t| t.get_name() == tool.get_name()) {
+            return Err(Error::validation("tools", format!("tool {} declared twice", tool.get_name())));
+        }
+        tools.push(tool);
+        Ok(())
+    }
+
+    /// `ToolChoice::Function` must name a declared tool, `Required` needs at least one
+    pub fn set_tool_choice(&mut self, tool_choice: ToolChoice) -> E {
+        let tools = self.tools.as_deref().unwrap_or_default();
+        match &tool_choice {
+            ToolChoice::Function(name) if !tools.iter().any(|t| t.get_name() == name) => {
+                return Err(Error::validation("tool_choice", format!("tool {} is not declared", name)));
+            },
+            ToolChoice::Required if tools.is_empty() => {
+                return Err(Error::validation("tool_choice", "required needs at least one tool"));
+            },
+            _ => {}
+        }
+        self.tool_choice = Some(tool_choice);
+        Ok(())
+    }
+
+    pub fn set_parallel_tool_calls(&mut self, parallel_tool_calls: bool) {
+        self.parallel_tool_calls = Some(parallel_tool_calls);
+    }
+
+    pub fn set_response_format(&mut self, response_format: ResponseFormat) {
+        self.response_format = Some(response_format);
+    }
+
     pub fn add_message(&mut self, message: Message<Sentence>) {
         self.messages.push(message);
     }
@@ -431,11 +754,130 @@ impl<Sentence: AsRef<str>> Body<Sentence> {
     pub fn get_user(&self) -> Option<&Sentence> {
         self.user.as_ref()
     }
+
+    /// Declared tools, empty if none
+    pub fn get_tools(&self) -> &[Tool] {
+        self.tools.as_deref().unwrap_or_default()
+    }
+
+    pub fn get_tool_choice(&self) -> Option<&ToolChoice> {
+        self.tool_choice.as_ref()
+    }
+
+    pub fn get_parallel_tool_calls(&self) -> Option<bool> {
+        self.parallel_tool_calls
+    }
+
+    pub fn get_response_format(&self) -> Option<&ResponseFormat> {
+        self.response_format.as_ref()
+    }
+
+    /// Rough prompt size, about 4 characters per token, a fixed cost per image plus a few tokens of framing per message \
+    /// Good enough for rate limiting, use a real tokenizer when exact counts matter
+    pub fn estimate_prompt_tokens(&self) -> u32 {
+        let chars: usize = self.messages.iter().map(|m| m.content.text_chars()).sum();
+        let images: u32 = self.messages.iter().map(|m| m.content.image_tokens()).sum();
+        (chars as u32).div_ceil(4) + images + 4 * self.messages.len() as u32 + 3
+    }
+
+    /// Prompt estimate plus every token the answers may take, i.e. `max_tokens` for each of the `n` choices
+    pub fn estimate_tokens(&self) -> u32 {
+        let completion = self.max_tokens.unwrap_or(0).saturating_mul(self.n.unwrap_or(1));
+        self.estimate_prompt_tokens().saturating_add(completion)
+    }
+
+    /// Check the request against what `registry` knows about its model \
+    /// Nothing here stops the request, the server may still accept it (e.g. limits raised since the registry was written)
+    pub fn validate(&self, registry: &Registry) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        let Some(info) = registry.get(self.model.as_str()) else {
+            warnings.push(Warning::new("model", format!("{} is not in the registry, limits are not checked", self.model)));
+            return warnings;
+        };
+        if let Some(max_tokens) = self.max_tokens {
+            if max_tokens > info.get_max_output_tokens() {
+                warnings.push(Warning::new("max_tokens", format!("{} is over the {} output tokens of {}", max_tokens, info.get_max_output_tokens(), self.model)));
+            }
+        }
+        if !self.get_tools().is_empty() && !info.get_features().tools {
+            warnings.push(Warning::new("tools", format!("{} does not support tools", self.model)));
+        }
+        if self.messages.iter().any(|m| m.content.has_images()) && !info.get_features().vision {
+            warnings.push(Warning::new("messages", format!("{} does not take images", self.model)));
+        }
+        if self.response_format.as_ref().map(ResponseFormat::is_json).unwrap_or(false) && !info.get_features().json_mode {
+            warnings.push(Warning::new("response_format", format!("{} does not support JSON answers", self.model)));
+        }
+        if self.estimate_tokens() > info.get_context_window() {
+            warnings.push(Warning::new("messages", format!("about {} tokens requested, {} has a context window of {}", self.estimate_tokens(), self.model, info.get_context_window())));
+        }
+        warnings
+    }
+}
+
+/// Something [`Body::validate`] found likely to fail or be cut short
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Warning {
+    field: &'static str,
+    message: String
+}
+
+impl Warning {
+    pub fn new(field: &'static str, message: impl Into<String>) -> Warning {
+        Warning { field, message: message.into() }
+    }
+
+    pub fn get_field(&self) -> &'static str {
+        self.field
+    }
+
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.field, self.message)
+    }
+}
+
+impl<Sentence: AsRef<str> + Clone> Body<Sentence> {
+    /// Fill every optional parameter that is not set with the one from `defaults` \
+    /// `model`, `messages` and `stream` always come from `self`
+    pub fn fill_defaults(&mut self, defaults: &Body<Sentence>) {
+        self.temperature = self.temperature.or(defaults.temperature);
+        self.top_p = self.top_p.or(defaults.top_p);
+        self.n = self.n.or(defaults.n);
+        self.max_tokens = self.max_tokens.or(defaults.max_tokens);
+        self.presence_penalty = self.presence_penalty.or(defaults.presence_penalty);
+        self.frequency_penalty = self.frequency_penalty.or(defaults.frequency_penalty);
+        if self.stop.is_none() {
+            self.stop = defaults.stop.clone();
+        }
+        if self.logit_bias.is_none() {
+            self.logit_bias = defaults.logit_bias.clone();
+        }
+        if self.user.is_none() {
+            self.user = defaults.user.clone();
+        }
+        if self.tools.is_none() {
+            self.tools = defaults.tools.clone();
+            self.tool_choice = self.tool_choice.clone().or_else(|| defaults.tool_choice.clone());
+        }
+        self.parallel_tool_calls = self.parallel_tool_calls.or(defaults.parallel_tool_calls);
+        if self.response_format.is_none() {
+            self.response_format = defaults.response_format.clone();
+        }
+    }
 }
 
 #[cfg(test)]
 mod request_tests {
-    use serde_test::{assert_tokens, Token};
+    use serde_json::json;
+    use serde_test::{assert_de_tokens, assert_tokens, Token};
+
+    use crate::datas::content::Detail;
 
     use super::*;
 
@@ -465,17 +907,72 @@ mod request_tests {
     fn test_se_de_models() {
         let a = Models::GPT35Turbo;
         let b = Models::GPT35Turbo0301;
+        let c = Models::Custom(String::from("llama-3-70b-instruct"));
 
         assert_tokens(&a, &[
-            Token::Enum { name: "Models" },
-            Token::Str("gpt-3.5-turbo"),
-            Token::Unit
+            Token::Str("gpt-3.5-turbo")
         ]);
         assert_tokens(&b, &[
-            Token::Enum { name: "Models" },
-            Token::Str("gpt-3.5-turbo-0301"),
-            Token::Unit
+            Token::Str("gpt-3.5-turbo-0301")
         ]);
+        assert_tokens(&c, &[
+            Token::Str("llama-3-70b-instruct")
+        ]);
+        assert_de_tokens(&Models::GPT4o, &[
+            Token::String("gpt-4o")
+        ]);
+        assert_eq!(Models::from("ft:gpt-4o-mini:acme::abc123").to_string(), "ft:gpt-4o-mini:acme::abc123");
+    }
+
+    #[test]
+    fn test_validate() {
+        let registry = Registry::builtin();
+        let mut body = Body::<&str>::new(Models::GPT4);
+        body.add_message(Message::new(Roles::User, "What is Earth"));
+        assert!(body.validate(registry).is_empty());
+        body.set_max_tokens(10_000).unwrap();
+        let warnings = body.validate(registry);
+        assert_eq!(warnings.iter().map(Warning::get_field).collect::<Vec<_>>(), vec!["max_tokens", "messages"]);
+        body.set_models(Models::GPT4o);
+        assert!(body.validate(registry).is_empty());
+        body.set_models(Models::from("my-local-model"));
+        assert_eq!(body.validate(registry)[0].get_field(), "model");
+    }
+
+    #[test]
+    fn test_message_parts() {
+        let mut body = Body::<&str>::new(Models::GPT35Turbo);
+        body.add_message(Message::parts(Roles::User, vec![
+            ContentPart::text("What is on this screenshot?"),
+            ContentPart::image_url("https://example.com/screen.png", Some(Detail::High)).unwrap()
+        ]));
+        assert_eq!(serde_json::to_value(&body).unwrap()["messages"][0], json!({
+            "role": "user",
+            "content": [
+                { "type": "text", "text": "What is on this screenshot?" },
+                { "type": "image_url", "image_url": { "url": "https://example.com/screen.png", "detail": "high" } }
+            ]
+        }));
+        assert_eq!(body.estimate_prompt_tokens(), 7 + 765 + 4 + 3);
+        assert_eq!(body.validate(Registry::builtin())[0].get_message(), "gpt-3.5-turbo does not take images");
+        body.set_models(Models::GPT4o);
+        assert!(body.validate(Registry::builtin()).is_empty());
+
+        let message: Message<String> = serde_json::from_str("{\"role\":\"user\",\"content\":[{\"type\":\"text\",\"text\":\"Hi\"}]}").unwrap();
+        assert_eq!(message.get_content().get_parts()[0].as_text(), Some(&String::from("Hi")));
+        let message: Message<String> = serde_json::from_str("{\"role\":\"user\",\"content\":\"Hi\"}").unwrap();
+        assert_eq!(message.get_content().as_text(), Some(&String::from("Hi")));
+    }
+
+    #[test]
+    fn test_model_info() {
+        let info = Models::GPT4o.info().unwrap();
+        assert_eq!(info.get_context_window(), 128_000);
+        assert_eq!(info.get_max_output_tokens(), 16_384);
+        assert!(info.get_features().tools && info.get_features().vision && info.get_features().json_mode);
+        assert!(!Models::O1Mini.info().unwrap().get_features().tools);
+        assert_eq!(Models::GPT35Turbo0301.info().unwrap().get_context_window(), 4_096);
+        assert_eq!(Models::Custom(String::from("local")).info(), None);
     }
 
     #[test]
@@ -499,6 +996,98 @@ mod request_tests {
             Token::Str("assistant"),
             Token::Unit
         ]);
+        assert_tokens(&Roles::Tool, &[
+            Token::Enum { name: "Roles" },
+            Token::Str("tool"),
+            Token::Unit
+        ]);
+    }
+
+    #[test]
+    fn test_se_de_tool_message() {
+        let a = Message::tool("call_1", "22 degrees");
+        let b = Message::<&str>::tool_calls(None, vec![ToolCall::new("call_1", "get_weather", "{}")]);
+
+        assert_tokens(&a, &[
+            Token::Struct { name: "Message", len: 3 },
+            Token::Str("role"),
+            Token::Enum { name: "Roles" },
+            Token::Str("tool"),
+            Token::Unit,
+            Token::Str("content"),
+            Token::BorrowedStr("22 degrees"),
+            Token::Str("tool_call_id"),
+            Token::Some,
+            Token::Str("call_1"),
+            Token::StructEnd
+        ]);
+        // content is null when the assistant only calls tools
+        assert_tokens(&b, &[
+            Token::Struct { name: "Message", len: 3 },
+            Token::Str("role"),
+            Token::Enum { name: "Roles" },
+            Token::Str("assistant"),
+            Token::Unit,
+            Token::Str("content"),
+            Token::Unit,
+            Token::Str("tool_calls"),
+            Token::Some,
+            Token::Seq { len: Some(1) },
+            Token::Struct { name: "ToolCall", len: 3 },
+            Token::Str("id"),
+            Token::Str("call_1"),
+            Token::Str("type"),
+            Token::UnitVariant { name: "ToolKind", variant: "function" },
+            Token::Str("function"),
+            Token::Struct { name: "FunctionCall", len: 2 },
+            Token::Str("name"),
+            Token::Str("get_weather"),
+            Token::Str("arguments"),
+            Token::Str("{}"),
+            Token::StructEnd,
+            Token::StructEnd,
+            Token::SeqEnd,
+            Token::StructEnd
+        ]);
+        let mut c = Message::new(Roles::User, "hi");
+        assert!(c.set_name("alice_01").is_ok());
+        assert!(c.set_name("alice smith").is_err());
+        assert_eq!(c.get_name(), Some("alice_01"));
+        assert_eq!(b.get_tool_calls()[0].get_function().get_name(), "get_weather");
+        assert!(c.get_tool_calls().is_empty());
+    }
+
+    #[test]
+    fn test_tool_calls_round_trip() {
+        let json = "{\"role\":\"assistant\",\"content\":null,\"tool_calls\":[{\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"get_weather\",\"arguments\":\"{}\"}}]}";
+        let message: Message<String> = serde_json::from_str(json).unwrap();
+        assert_eq!(message.get_content(), &Content::Null);
+        assert_eq!(message.get_content().to_text(), "");
+        assert_eq!(serde_json::to_string(&message).unwrap(), json);
+        let echoed = Message::tool_calls(message.get_content().as_text().cloned(), message.get_tool_calls().to_vec());
+        assert_eq!(serde_json::to_string(&echoed).unwrap(), json);
+    }
+
+    #[test]
+    fn test_body_tools() {
+        let mut body = Body::<String>::new(Models::GPT4o);
+        assert!(body.set_tool_choice(ToolChoice::Required).is_err());
+        let tool = Tool::function("get_weather", "Current weather of a city", json!({"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]})).unwrap();
+        assert!(body.add_tool(tool.clone()).is_ok());
+        assert!(body.add_tool(tool).is_err());
+        assert!(body.set_tool_choice(ToolChoice::Function(String::from("get_time"))).is_err());
+        assert!(body.set_tool_choice(ToolChoice::Function(String::from("get_weather"))).is_ok());
+        body.set_parallel_tool_calls(false);
+        body.add_message(Message::new(Roles::User, String::from("Weather in Paris?")));
+        body.add_message(Message::tool_calls(None, vec![ToolCall::new("call_1", "get_weather", "{\"city\":\"Paris\"}")]));
+        body.add_message(Message::tool("call_1", String::from("22 degrees")));
+
+        let json = serde_json::to_string(&body).unwrap();
+        assert_eq!(json, "{\"model\":\"gpt-4o\",\"messages\":[{\"role\":\"user\",\"content\":\"Weather in Paris?\"},{\"role\":\"assistant\",\"content\":null,\"tool_calls\":[{\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"get_weather\",\"arguments\":\"{\\\"city\\\":\\\"Paris\\\"}\"}}]},{\"role\":\"tool\",\"content\":\"22 degrees\",\"tool_call_id\":\"call_1\"}],\"tools\":[{\"type\":\"function\",\"function\":{\"name\":\"get_weather\",\"description\":\"Current weather of a city\",\"parameters\":{\"properties\":{\"city\":{\"type\":\"string\"}},\"required\":[\"city\"],\"type\":\"object\"}}}],\"tool_choice\":{\"type\":\"function\",\"function\":{\"name\":\"get_weather\"}},\"parallel_tool_calls\":false}");
+        assert_eq!(serde_json::from_str::<Body<String>>(&json).unwrap(), body);
+
+        body.set_models(Models::O1Mini);
+        assert_eq!(body.validate(Registry::builtin())[0].get_field(), "tools");
     }
 
     #[test]
@@ -535,9 +1124,7 @@ mod request_tests {
         assert_tokens(&a, &[
             Token::Struct { name: "Body", len: 2 },
             Token::Str("model"),
-            Token::Enum { name: "Models" },
             Token::Str("gpt-3.5-turbo"),
-            Token::Unit,
             Token::Str("messages"),
             Token::Seq { len: Some(0) },
             Token::SeqEnd,
@@ -577,6 +1164,35 @@ mod request_tests {
         assert_eq!(serbody, "{\"model\":\"gpt-3.5-turbo\",\"messages\":[{\"role\":\"system\",\"content\":\"Earth is be like\"},{\"role\":\"user\",\"content\":\"What is Earth\"}],\"temperature\":0.1,\"top_p\":0.3,\"n\":4,\"stream\":false,\"stop\":[\"a\",\"b\",\"c\",\"d\"],\"max_tokens\":100,\"presence_penalty\":-2.0,\"frequency_penalty\":-2.0,\"logit_bias\":{\"5044\":-33}}");
     }
 
+    #[test]
+    fn test_fill_defaults() {
+        let mut defaults = Body::<&str>::default();
+        defaults.set_temperature(0.2).unwrap();
+        defaults.set_max_tokens(64).unwrap();
+        defaults.set_user("batch");
+        defaults.add_message(Message::new(Roles::System, "ignored"));
+        let mut body = Body::<&str>::new(Models::GPT35Turbo0301);
+        body.set_temperature(1.5).unwrap();
+        body.add_message(Message::new(Roles::User, "What is Earth"));
+        body.fill_defaults(&defaults);
+        assert_eq!(body.get_temperature(), Some(1.5));
+        assert_eq!(body.get_max_tokens(), Some(64));
+        assert_eq!(body.get_user(), Some(&"batch"));
+        assert_eq!(body.get_model(), Models::GPT35Turbo0301);
+        assert_eq!(body.get_messages().len(), 1);
+    }
+
+    #[test]
+    fn test_estimate_tokens() {
+        let mut body = Body::<&str>::default();
+        assert_eq!(body.estimate_tokens(), 3);
+        body.add_message(Message::new(Roles::User, "What is Earth"));
+        assert_eq!(body.estimate_prompt_tokens(), 4 + 4 + 3);
+        body.set_max_tokens(100).unwrap();
+        body.set_n(2).unwrap();
+        assert_eq!(body.estimate_tokens(), 11 + 200);
+    }
+
     #[test]
     fn test_chat_login() {
         let mut token = ChatLogin::<&str>::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", Some("test")).unwrap();
@@ -587,4 +1203,34 @@ mod request_tests {
         assert!(token.set_auth("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX;X").is_err());
         assert!(token.set_auth("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXaXXXX6XX3XxX").is_ok());
     }
+
+    #[test]
+    fn test_key_policy() {
+        let project = "sk-proj-Ab3_dE-fGhIjKlMnOpQrStUvWxYz0123456789_-AbCdEfGh";
+        let mut token = ChatLogin::<&str>::new(project, None).unwrap();
+        assert_eq!(token.get_auth().expose(), project);
+        assert!(token.set_auth("sk-svcacct-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX").is_ok());
+        assert_eq!(token.get_auth().expose(), "sk-svcacct-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX");
+        assert!(token.set_auth("Bearer ").is_err());
+        assert!(token.set_auth("Bearer").is_err());
+        assert!(token.set_auth("local-key").is_err());
+        assert!(token.set_auth("sk-proj-Ab3 dE").is_err());
+
+        assert!(!format!("{:?}", token).contains("XXXXXXXX"));
+
+        let mut token = ChatLogin::<&str>::with_policy("local-key", None, KeyPolicy::Permissive).unwrap();
+        assert_eq!(token.get_auth().expose(), "local-key");
+        assert!(token.set_auth("Bearer anything.goes").is_ok());
+        assert!(token.set_auth("two words").is_err());
+        assert!(token.set_auth("").is_err());
+
+        let policy = KeyPolicy::custom(|key| match key.starts_with("gw-") {
+            true => Ok(()),
+            false => Err(String::from("gateway keys start with gw-"))
+        });
+        let token = ChatLogin::<&str>::with_policy("gw-123", None, policy.clone());
+        assert!(token.is_ok());
+        let err = ChatLogin::<&str>::with_policy("sk-123", None, policy).err().unwrap();
+        assert!(err.to_string().contains("gateway keys start with gw-"));
+    }
 }
\ No newline at end of file
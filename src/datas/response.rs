@@ -1,35 +1,256 @@
+use std::fmt;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
 use serde::Deserialize;
-use crate::datas::request::{Message};
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+use serde::de::DeserializeOwned;
+use crate::datas::registry::Cost;
+use crate::datas::registry::Registry;
+use crate::datas::request::{Message, Models, Roles};
+use crate::datas::tool::ToolCallDelta;
+use crate::datas::usage::Usage;
+use crate::error::Error;
+use crate::schema;
+use crate::schema::JsonSchema;
 
 #[derive(Deserialize, PartialEq, Eq, Debug)]
+#[serde(bound(deserialize = "Sentence: Deserialize<'de> + Default"))]
 pub struct Resp<Sentence> {
     id: Sentence,
     object: Sentence,
     created: u64,
+    /// Model that answered, may be more precise than the one asked for (e.g. a dated snapshot)
+    model: Option<Models>,
     choices: Vec<Choice<Sentence>>,
     usage: Usage
 }
 
 #[derive(Deserialize, PartialEq, Eq, Debug)]
-struct Usage {
-    prompt_tokens: u16,
-    completion_tokens: u16,
-    total_tokens: u16
+#[serde(bound(deserialize = "Sentence: Deserialize<'de> + Default"))]
+pub struct Choice<Sentence> {
+    index: u64,
+    /// Carries the tool calls when `finish_reason` is [`FinishReason::ToolCalls`]
+    message: Message<Sentence>,
+    finish_reason: FinishReason
+}
+
+/// Why the model stopped writing a choice
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum FinishReason {
+    /// Natural end of the answer or a stop sequence
+    Stop,
+    /// `max_tokens` or the context window was reached, the answer is cut
+    Length,
+    /// The model called tools
+    ToolCalls,
+    /// Content was left out by the content filter
+    ContentFilter,
+    /// A reason this crate doesn't know yet
+    Unknown(String)
+}
+
+impl FinishReason {
+    pub fn as_str(&self) -> &str {
+        match self {
+            FinishReason::Stop => "stop",
+            FinishReason::Length => "length",
+            FinishReason::ToolCalls => "tool_calls",
+            FinishReason::ContentFilter => "content_filter",
+            FinishReason::Unknown(reason) => reason
+        }
+    }
+}
+
+impl From<&str> for FinishReason {
+    fn from(reason: &str) -> Self {
+        match reason {
+            "stop" => FinishReason::Stop,
+            "length" => FinishReason::Length,
+            "tool_calls" => FinishReason::ToolCalls,
+            "content_filter" => FinishReason::ContentFilter,
+            other => FinishReason::Unknown(other.to_string())
+        }
+    }
+}
+
+impl fmt::Display for FinishReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for FinishReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for FinishReason {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(|reason| FinishReason::from(reason.as_str()))
+    }
+}
+
+impl<Sentence> Choice<Sentence> {
+    pub fn get_index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn get_message(&self) -> &Message<Sentence> {
+        &self.message
+    }
+
+    pub fn get_finish_reason(&self) -> &FinishReason {
+        &self.finish_reason
+    }
+}
+
+impl<Sentence> Resp<Sentence> {
+    pub fn get_id(&self) -> &Sentence {
+        &self.id
+    }
+
+    pub fn get_object(&self) -> &Sentence {
+        &self.object
+    }
+
+    /// When the server created the response, to the second
+    pub fn get_created(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.created)
+    }
+
+    pub fn get_choices(&self) -> &Vec<Choice<Sentence>> {
+        &self.choices
+    }
+
+    pub fn iter_choices(&self) -> std::slice::Iter<'_, Choice<Sentence>> {
+        self.choices.iter()
+    }
+
+    pub fn get_model(&self) -> Option<&Models> {
+        self.model.as_ref()
+    }
+
+    pub fn get_usage(&self) -> &Usage {
+        &self.usage
+    }
+
+    /// Price of this response with the prices of `registry`, None if the response doesn't name
+    /// its model or the registry has no prices for it
+    pub fn cost(&self, registry: &Registry) -> Option<Cost> {
+        self.usage.cost(registry, self.model.as_ref()?.as_str())
+    }
+
+    pub(crate) fn total_tokens(&self) -> u32 {
+        self.usage.get_total_tokens()
+    }
+}
+
+impl<Sentence: AsRef<str>> Resp<Sentence> {
+    /// Text of the first choice, None if there is no choice or its content is made of parts
+    pub fn get_first_content(&self) -> Option<&Sentence> {
+        self.choices.first()?.get_message().get_content().as_text()
+    }
+
+    /// Content of the first choice checked against the schema of `T`, then parsed into it \
+    /// A refusal is [`Error::Refusal`], JSON not following the schema [`Error::SchemaMismatch`]
+    pub fn parse_content<T: JsonSchema + DeserializeOwned>(&self) -> Result<T, Error> {
+        let message = self.choices.first().ok_or_else(|| Error::Decode(String::from("response has no choices")))?.get_message();
+        if let Some(refusal) = message.get_refusal() {
+            return Err(Error::Refusal(refusal.to_string()));
+        }
+        schema::from_str(&message.get_content().to_text())
+    }
+}
+
+/// One `data:` event of a streamed chat completion
+#[derive(Deserialize, PartialEq, Eq, Debug)]
+pub struct Chunk<Sentence> {
+    id: Sentence,
+    object: Sentence,
+    created: u64,
+    model: Option<Models>,
+    choices: Vec<ChunkChoice<Sentence>>,
+    /// Only on the last chunk, which has no choices, when `stream_options.include_usage` was asked for
+    usage: Option<Usage>
 }
 
 #[derive(Deserialize, PartialEq, Eq, Debug)]
-struct Choice<Sentence> {
+pub struct ChunkChoice<Sentence> {
     index: u64,
-    message: Message<Sentence>,
-    finish_reason: Sentence
+    delta: Delta<Sentence>,
+    /// Only set on the last chunk of each choice
+    finish_reason: Option<FinishReason>
+}
+
+/// Part of the message generated since the previous chunk \
+/// `role` only comes with the first chunk, `content` is missing on the last one
+#[derive(Deserialize, PartialEq, Eq, Debug)]
+pub struct Delta<Sentence> {
+    role: Option<Roles>,
+    content: Option<Sentence>,
+    tool_calls: Option<Vec<ToolCallDelta>>
+}
+
+impl<Sentence> Chunk<Sentence> {
+    pub fn get_id(&self) -> &Sentence {
+        &self.id
+    }
+
+    pub fn get_created(&self) -> u64 {
+        self.created
+    }
+
+    pub fn get_model(&self) -> Option<&Models> {
+        self.model.as_ref()
+    }
+
+    pub fn get_choices(&self) -> &Vec<ChunkChoice<Sentence>> {
+        &self.choices
+    }
+
+    pub fn get_usage(&self) -> Option<&Usage> {
+        self.usage.as_ref()
+    }
+}
+
+impl<Sentence> ChunkChoice<Sentence> {
+    pub fn get_index(&self) -> u64 {
+        self.index
+    }
+
+    pub fn get_delta(&self) -> &Delta<Sentence> {
+        &self.delta
+    }
+
+    pub fn get_finish_reason(&self) -> Option<&FinishReason> {
+        self.finish_reason.as_ref()
+    }
+}
+
+impl<Sentence> Delta<Sentence> {
+    pub fn get_role(&self) -> Option<&Roles> {
+        self.role.as_ref()
+    }
+
+    pub fn get_content(&self) -> Option<&Sentence> {
+        self.content.as_ref()
+    }
+
+    /// Pieces of tool calls, empty if none
+    pub fn get_tool_calls(&self) -> &[ToolCallDelta] {
+        self.tool_calls.as_deref().unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
 mod response_test {
     use serde_test::{assert_de_tokens, Token};
 
-    use crate::datas::request::Roles;
-
     use super::*;
 
     #[test]
@@ -37,7 +258,7 @@ mod response_test {
         let choice = Choice::<String> {
             index: 0,
             message: Message::new(Roles::Assistant, "\nTest".to_string()),
-            finish_reason: "stop".to_string()
+            finish_reason: FinishReason::Stop
         };
 
         assert_de_tokens(&choice, &[
@@ -61,11 +282,7 @@ mod response_test {
 
     #[test]
     fn test_de_usage() {
-        let usage = Usage {
-            prompt_tokens: 1,
-            completion_tokens: 1,
-            total_tokens: 2
-        };
+        let usage = Usage::new(1, 1);
         
         assert_de_tokens(&usage, &[
             Token::Struct { name: "Usage", len: 3 },
@@ -85,18 +302,15 @@ mod response_test {
             id: "chatcmpl-123",
             object: "chat.completion",
             created: 161444444,
+            model: None,
             choices: vec![
                 Choice {
                     index: 0,
                     message: Message::new(Roles::Assistant, "\nEarth is"),
-                    finish_reason: "stop"
+                    finish_reason: FinishReason::Stop
                 }
             ],
-            usage: Usage {
-                prompt_tokens: 1,
-                completion_tokens: 1,
-                total_tokens: 2
-            }
+            usage: Usage::new(1, 1)
         };
 
         assert_de_tokens(&resp, &[
@@ -138,4 +352,59 @@ mod response_test {
         ])
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_deserialize_chunk() {
+        let first: Chunk<String> = serde_json::from_str("{\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1677652288,\"model\":\"gpt-3.5-turbo\",\"choices\":[{\"index\":0,\"delta\":{\"role\":\"assistant\"},\"finish_reason\":null}]}").unwrap();
+        let last: Chunk<String> = serde_json::from_str("{\"id\":\"chatcmpl-123\",\"object\":\"chat.completion.chunk\",\"created\":1677652288,\"choices\":[{\"index\":0,\"delta\":{},\"finish_reason\":\"stop\"}]}").unwrap();
+
+        assert_eq!(first.get_choices()[0].get_delta().get_role(), Some(&Roles::Assistant));
+        assert_eq!(first.get_choices()[0].get_delta().get_content(), None);
+        assert_eq!(first.get_choices()[0].get_finish_reason(), None);
+        assert_eq!(last.get_choices()[0].get_delta().get_role(), None);
+        assert_eq!(last.get_choices()[0].get_finish_reason(), Some(&FinishReason::Stop));
+        assert_eq!(first.get_model(), Some(&Models::GPT35Turbo));
+        assert_eq!(last.get_model(), None);
+    }
+
+    #[test]
+    fn test_deserialize_tool_calls() {
+        let resp: Resp<String> = serde_json::from_str("{\"id\":\"chatcmpl-1\",\"object\":\"chat.completion\",\"created\":1,\"model\":\"gpt-4o\",\"choices\":[{\"index\":0,\"message\":{\"role\":\"assistant\",\"content\":null,\"tool_calls\":[{\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"get_weather\",\"arguments\":\"{\\\"city\\\":\\\"Paris\\\"}\"}},{\"id\":\"call_2\",\"type\":\"function\",\"function\":{\"name\":\"get_time\",\"arguments\":\"{}\"}}]},\"finish_reason\":\"tool_calls\"}],\"usage\":{\"prompt_tokens\":50,\"completion_tokens\":30,\"total_tokens\":80}}").unwrap();
+        let choice = &resp.get_choices()[0];
+        assert_eq!(choice.get_finish_reason(), &FinishReason::ToolCalls);
+        assert_eq!(choice.get_message().get_content().to_text(), "");
+        let calls = choice.get_message().get_tool_calls();
+        assert_eq!(calls.len(), 2);
+        assert_eq!((calls[0].get_id(), calls[0].get_function().get_name()), ("call_1", "get_weather"));
+        assert_eq!(calls[0].get_function().parse_arguments().unwrap()["city"], "Paris");
+
+        let chunk: Chunk<String> = serde_json::from_str("{\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"choices\":[{\"index\":0,\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"call_1\",\"type\":\"function\",\"function\":{\"name\":\"get_weather\",\"arguments\":\"{\\\"ci\"}}]},\"finish_reason\":null}]}").unwrap();
+        let delta = &chunk.get_choices()[0].get_delta().get_tool_calls()[0];
+        assert_eq!((delta.get_index(), delta.get_id(), delta.get_name(), delta.get_arguments()), (0, Some("call_1"), Some("get_weather"), Some("{\"ci")));
+    }
+
+    #[test]
+    fn test_resp_accessors() {
+        let resp: Resp<String> = serde_json::from_str("{\"id\":\"chatcmpl-9\",\"object\":\"chat.completion\",\"created\":1700000000,\"choices\":[{\"index\":0,\"message\":{\"role\":\"assistant\",\"content\":\"Forty\"},\"finish_reason\":\"length\"},{\"index\":1,\"message\":{\"role\":\"assistant\",\"content\":\"\"},\"finish_reason\":\"end_turn\"}],\"usage\":{\"prompt_tokens\":3,\"completion_tokens\":2,\"total_tokens\":5}}").unwrap();
+        assert_eq!(resp.get_id(), "chatcmpl-9");
+        assert_eq!(resp.get_created().duration_since(UNIX_EPOCH).unwrap(), Duration::from_secs(1_700_000_000));
+        assert_eq!(resp.get_first_content().map(String::as_str), Some("Forty"));
+        assert_eq!(resp.get_usage().get_total_tokens(), 5);
+        let reasons: Vec<&FinishReason> = resp.iter_choices().map(Choice::get_finish_reason).collect();
+        assert_eq!(reasons, vec![&FinishReason::Length, &FinishReason::Unknown(String::from("end_turn"))]);
+        assert_eq!(reasons[1].to_string(), "end_turn");
+        assert_eq!(serde_json::to_string(&FinishReason::ContentFilter).unwrap(), "\"content_filter\"");
+    }
+
+    #[test]
+    fn test_unknown_model() {
+        let resp: Resp<String> = serde_json::from_str("{\"id\":\"cmpl-1\",\"object\":\"chat.completion\",\"created\":1,\"model\":\"qwen2.5-7b-instruct\",\"choices\":[],\"usage\":{\"prompt_tokens\":1,\"completion_tokens\":1,\"total_tokens\":2}}").unwrap();
+        assert_eq!(resp.get_model(), Some(&Models::Custom(String::from("qwen2.5-7b-instruct"))));
+        assert_eq!(resp.cost(Registry::builtin()), None);
+        let resp: Resp<String> = serde_json::from_str("{\"id\":\"cmpl-1\",\"object\":\"chat.completion\",\"created\":1,\"model\":\"gpt-4-0613\",\"choices\":[],\"usage\":{\"prompt_tokens\":1000,\"completion_tokens\":500,\"total_tokens\":1500}}").unwrap();
+        assert!((resp.cost(Registry::builtin()).unwrap().total() - 0.06).abs() < 1e-12);
+        // over 65535 tokens, half of the prompt from the cache
+        let resp: Resp<String> = serde_json::from_str("{\"id\":\"cmpl-1\",\"object\":\"chat.completion\",\"created\":1,\"model\":\"gpt-4o\",\"choices\":[],\"usage\":{\"prompt_tokens\":100000,\"completion_tokens\":1000,\"total_tokens\":101000,\"prompt_tokens_details\":{\"cached_tokens\":50000}}}").unwrap();
+        assert_eq!(resp.get_usage().get_cached_tokens(), 50_000);
+        assert!((resp.cost(Registry::builtin()).unwrap().total() - (0.125 + 0.0625 + 0.01)).abs() < 1e-12);
+    }
+}
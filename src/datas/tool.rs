@@ -0,0 +1,348 @@
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde::Serializer;
+use serde::ser::SerializeStruct;
+use serde_json::Value;
+use serde_with::skip_serializing_none;
+
+use crate::error::Error;
+use crate::schema;
+use crate::schema::JsonSchema;
+
+/// The API caps the number of tools of one request
+pub const MAX_TOOLS: usize = 128;
+
+/*
+ * ======
+ * TOOL DEFINITIONS (REQUEST)
+ * ======
+ */
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolKind {
+    #[default]
+    Function
+}
+
+/// A function the model may call
+#[skip_serializing_none]
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct FunctionDef {
+    /// a-z, A-Z, 0-9, underscores and dashes, at most 64 characters
+    name: String,
+    /// What the function does, the model uses it to choose when and how to call it
+    description: Option<String>,
+    /// JSON schema of the arguments object, omit for a function without arguments
+    parameters: Option<Value>,
+    /// Make the model follow `parameters` exactly (structured outputs)
+    strict: Option<bool>
+}
+
+impl FunctionDef {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn get_parameters(&self) -> Option<&Value> {
+        self.parameters.as_ref()
+    }
+
+    pub fn get_strict(&self) -> Option<bool> {
+        self.strict
+    }
+}
+
+/// Tool declared in a request, only functions for now
+#[derive(Deserialize, Serialize, Clone, PartialEq, Debug)]
+pub struct Tool {
+    #[serde(rename = "type")]
+    kind: ToolKind,
+    function: FunctionDef
+}
+
+pub(crate) fn check_function_name(name: &str) -> Result<(), Error> {
+    match !name.is_empty() && name.len() <= 64 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        true => Ok(()),
+        false => Err(Error::validation("tools", format!("function name {:?} must be 1 to 64 of a-z, A-Z, 0-9, _ and -", name)))
+    }
+}
+
+impl Tool {
+    /// `parameters` must be a JSON schema object, e.g. `{"type": "object", "properties": {...}, "required": [...]}`
+    pub fn function(name: impl Into<String>, description: impl Into<String>, parameters: Value) -> Result<Tool, Error> {
+        let name = name.into();
+        check_function_name(&name)?;
+        if !parameters.is_object() {
+            return Err(Error::validation("tools", format!("parameters of {} must be a JSON schema object", name)));
+        }
+        let description = Some(description.into()).filter(|d| !d.is_empty());
+        Ok(Tool { kind: ToolKind::Function, function: FunctionDef { name, description, parameters: Some(parameters), strict: None } })
+    }
+
+    /// Function taking a `T`, strict so the model follows the schema of `T` exactly
+    pub fn typed<T: JsonSchema>(name: impl Into<String>, description: impl Into<String>) -> Result<Tool, Error> {
+        let mut tool = Tool::function(name, description, T::schema())?;
+        tool.set_strict(true);
+        Ok(tool)
+    }
+
+    pub fn set_strict(&mut self, strict: bool) {
+        self.function.strict = Some(strict);
+    }
+
+    pub fn get_kind(&self) -> ToolKind {
+        self.kind
+    }
+
+    pub fn get_function(&self) -> &FunctionDef {
+        &self.function
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.function.name
+    }
+}
+
+/// Whether and which tool the model must call
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ToolChoice {
+    /// Never call a tool
+    None,
+    /// Let the model decide, the default when tools are given
+    Auto,
+    /// Call at least one tool
+    Required,
+    /// Call this function
+    Function(String)
+}
+
+impl Serialize for ToolChoice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            ToolChoice::None => serializer.serialize_str("none"),
+            ToolChoice::Auto => serializer.serialize_str("auto"),
+            ToolChoice::Required => serializer.serialize_str("required"),
+            ToolChoice::Function(name) => {
+                let mut state = serializer.serialize_struct("ToolChoice", 2)?;
+                state.serialize_field("type", &ToolKind::Function)?;
+                state.serialize_field("function", &NamedFunction { name: name.clone() })?;
+                state.end()
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct NamedFunction {
+    name: String
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawToolChoice {
+    Mode(String),
+    Named {
+        #[serde(rename = "type")]
+        _kind: ToolKind,
+        function: NamedFunction
+    }
+}
+
+impl<'de> Deserialize<'de> for ToolChoice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match RawToolChoice::deserialize(deserializer)? {
+            RawToolChoice::Mode(mode) => match mode.as_str() {
+                "none" => Ok(ToolChoice::None),
+                "auto" => Ok(ToolChoice::Auto),
+                "required" => Ok(ToolChoice::Required),
+                other => Err(serde::de::Error::unknown_variant(other, &["none", "auto", "required"]))
+            },
+            RawToolChoice::Named { function, .. } => Ok(ToolChoice::Function(function.name))
+        }
+    }
+}
+
+/*
+ * ======
+ * TOOL CALLS (RESPONSE)
+ * ======
+ */
+
+/// Function the model wants called
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct FunctionCall {
+    name: String,
+    /// JSON object as generated by the model, it may be invalid or miss required fields
+    arguments: String
+}
+
+impl FunctionCall {
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn get_arguments(&self) -> &str {
+        &self.arguments
+    }
+
+    /// Arguments parsed as JSON
+    pub fn parse_arguments(&self) -> Result<Value, Error> {
+        Ok(serde_json::from_str(&self.arguments)?)
+    }
+
+    /// Arguments checked against the schema of `T`, then deserialized into it
+    pub fn parse_arguments_as<T: JsonSchema + DeserializeOwned>(&self) -> Result<T, Error> {
+        schema::from_str(&self.arguments)
+    }
+}
+
+/// One call requested by an assistant message, answer it with a `Tool` message quoting `id`
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct ToolCall {
+    id: String,
+    #[serde(rename = "type")]
+    kind: ToolKind,
+    function: FunctionCall
+}
+
+impl ToolCall {
+    pub fn new(id: impl Into<String>, name: impl Into<String>, arguments: impl Into<String>) -> ToolCall {
+        ToolCall { id: id.into(), kind: ToolKind::Function, function: FunctionCall { name: name.into(), arguments: arguments.into() } }
+    }
+
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn get_kind(&self) -> ToolKind {
+        self.kind
+    }
+
+    pub fn get_function(&self) -> &FunctionCall {
+        &self.function
+    }
+}
+
+/// Piece of a tool call in a streamed chunk, pieces with the same `index` add up to one [`ToolCall`]
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ToolCallDelta {
+    index: u32,
+    /// Only on the first piece
+    id: Option<String>,
+    function: Option<FunctionCallDelta>
+}
+
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct FunctionCallDelta {
+    /// Only on the first piece
+    name: Option<String>,
+    /// Next characters of the arguments
+    arguments: Option<String>
+}
+
+impl ToolCallDelta {
+    pub fn get_index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn get_id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn get_name(&self) -> Option<&str> {
+        self.function.as_ref()?.name.as_deref()
+    }
+
+    pub fn get_arguments(&self) -> Option<&str> {
+        self.function.as_ref()?.arguments.as_deref()
+    }
+}
+
+#[cfg(test)]
+mod tool_tests {
+    use serde_json::json;
+    use serde_test::{assert_de_tokens, assert_tokens, Token};
+
+    use super::*;
+
+    #[test]
+    fn test_se_de_tool() {
+        let tool = Tool::function("get_weather", "", json!({})).unwrap();
+        assert_tokens(&tool, &[
+            Token::Struct { name: "Tool", len: 2 },
+            Token::Str("type"),
+            Token::UnitVariant { name: "ToolKind", variant: "function" },
+            Token::Str("function"),
+            Token::Struct { name: "FunctionDef", len: 2 },
+            Token::Str("name"),
+            Token::Str("get_weather"),
+            Token::Str("parameters"),
+            Token::Some,
+            Token::Map { len: Some(0) },
+            Token::MapEnd,
+            Token::StructEnd,
+            Token::StructEnd
+        ]);
+        assert!(Tool::function("get weather", "", json!({})).is_err());
+        assert!(Tool::function("x".repeat(65), "", json!({})).is_err());
+        assert!(Tool::function("get_weather", "", json!("string")).is_err());
+    }
+
+    #[test]
+    fn test_se_de_tool_choice() {
+        assert_tokens(&ToolChoice::Auto, &[Token::Str("auto")]);
+        assert_tokens(&ToolChoice::Required, &[Token::Str("required")]);
+        assert_de_tokens(&ToolChoice::None, &[Token::Str("none")]);
+        let named = ToolChoice::Function(String::from("get_weather"));
+        assert_eq!(serde_json::to_string(&named).unwrap(), "{\"type\":\"function\",\"function\":{\"name\":\"get_weather\"}}");
+        assert_eq!(serde_json::from_str::<ToolChoice>("{\"type\":\"function\",\"function\":{\"name\":\"get_weather\"}}").unwrap(), named);
+        assert!(serde_json::from_str::<ToolChoice>("\"sometimes\"").is_err());
+    }
+
+    #[test]
+    fn test_se_de_tool_call() {
+        let call = ToolCall::new("call_1", "get_weather", "{\"city\":\"Paris\"}");
+        assert_tokens(&call, &[
+            Token::Struct { name: "ToolCall", len: 3 },
+            Token::Str("id"),
+            Token::Str("call_1"),
+            Token::Str("type"),
+            Token::UnitVariant { name: "ToolKind", variant: "function" },
+            Token::Str("function"),
+            Token::Struct { name: "FunctionCall", len: 2 },
+            Token::Str("name"),
+            Token::Str("get_weather"),
+            Token::Str("arguments"),
+            Token::Str("{\"city\":\"Paris\"}"),
+            Token::StructEnd,
+            Token::StructEnd
+        ]);
+        assert_eq!(call.get_function().parse_arguments().unwrap()["city"], "Paris");
+        assert!(ToolCall::new("call_2", "f", "{\"city\":").get_function().parse_arguments().is_err());
+    }
+
+    /// Weather of a city
+    #[derive(Deserialize, JsonSchema, PartialEq, Debug)]
+    struct GetWeather {
+        city: String,
+        days: Option<u8>
+    }
+
+    #[test]
+    fn test_typed_tool() {
+        let tool = Tool::typed::<GetWeather>("get_weather", "").unwrap();
+        assert_eq!(tool.get_function().get_strict(), Some(true));
+        assert_eq!(tool.get_function().get_parameters(), Some(&GetWeather::schema()));
+        let call = ToolCall::new("call_1", "get_weather", "{\"city\":\"Paris\",\"days\":null}");
+        assert_eq!(call.get_function().parse_arguments_as::<GetWeather>().unwrap(), GetWeather { city: String::from("Paris"), days: None });
+        let call = ToolCall::new("call_2", "get_weather", "{\"city\":\"Paris\"}");
+        assert!(matches!(call.get_function().parse_arguments_as::<GetWeather>(), Err(Error::SchemaMismatch { .. })));
+    }
+}
@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::iter::Sum;
+use std::ops::Add;
+use std::ops::AddAssign;
+
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde_with::skip_serializing_none;
+
+use super::registry::Cost;
+use super::registry::Registry;
+use super::response::Resp;
+
+/// Some servers send null for counts they don't track
+fn null_as_zero<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    Ok(Option::<u32>::deserialize(deserializer)?.unwrap_or(0))
+}
+
+/// Breakdown of the prompt tokens
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PromptTokensDetails {
+    /// Served from the prompt cache, billed at the cached price
+    #[serde(default, deserialize_with = "null_as_zero")]
+    cached_tokens: u32,
+    #[serde(default, deserialize_with = "null_as_zero")]
+    audio_tokens: u32
+}
+
+/// Breakdown of the completion tokens
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct CompletionTokensDetails {
+    /// Spent thinking by reasoning models, billed but not part of the answer
+    #[serde(default, deserialize_with = "null_as_zero")]
+    reasoning_tokens: u32,
+    #[serde(default, deserialize_with = "null_as_zero")]
+    audio_tokens: u32
+}
+
+/// Tokens billed for a request \
+/// Add them up with `+`, `+=` or `sum()` into a [`TotalUsage`], or per model with [`UsageByModel`].
+#[skip_serializing_none]
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct Usage {
+    prompt_tokens: u32,
+    completion_tokens: u32,
+    total_tokens: u32,
+    prompt_tokens_details: Option<PromptTokensDetails>,
+    completion_tokens_details: Option<CompletionTokensDetails>
+}
+
+impl Usage {
+    pub fn new(prompt_tokens: u32, completion_tokens: u32) -> Usage {
+        Usage { prompt_tokens, completion_tokens, total_tokens: prompt_tokens + completion_tokens, ..Usage::default() }
+    }
+
+    pub fn get_prompt_tokens(&self) -> u32 {
+        self.prompt_tokens
+    }
+
+    pub fn get_completion_tokens(&self) -> u32 {
+        self.completion_tokens
+    }
+
+    pub fn get_total_tokens(&self) -> u32 {
+        self.total_tokens
+    }
+
+    /// Prompt tokens served from the cache, part of `prompt_tokens`
+    pub fn get_cached_tokens(&self) -> u32 {
+        self.prompt_tokens_details.map(|d| d.cached_tokens).unwrap_or(0)
+    }
+
+    /// Hidden reasoning tokens, part of `completion_tokens`
+    pub fn get_reasoning_tokens(&self) -> u32 {
+        self.completion_tokens_details.map(|d| d.reasoning_tokens).unwrap_or(0)
+    }
+
+    /// Audio input tokens, part of `prompt_tokens`
+    pub fn get_prompt_audio_tokens(&self) -> u32 {
+        self.prompt_tokens_details.map(|d| d.audio_tokens).unwrap_or(0)
+    }
+
+    /// Audio output tokens, part of `completion_tokens`
+    pub fn get_completion_audio_tokens(&self) -> u32 {
+        self.completion_tokens_details.map(|d| d.audio_tokens).unwrap_or(0)
+    }
+
+    /// Price of these tokens with the prices `registry` has for `model`, the cached tokens at the cached price
+    pub fn cost(&self, registry: &Registry, model: &str) -> Option<Cost> {
+        registry.cost(model, self.prompt_tokens.into(), self.get_cached_tokens().into(), self.completion_tokens.into())
+    }
+}
+
+/// Tokens of many requests added up, counted in u64 so that long runs don't overflow \
+/// Comes from `sum()`, `+` and `+=` over [`Usage`]s, and from [`UsageByModel`].
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TotalUsage {
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    total_tokens: u64,
+    cached_tokens: u64,
+    reasoning_tokens: u64,
+    prompt_audio_tokens: u64,
+    completion_audio_tokens: u64
+}
+
+impl TotalUsage {
+    pub fn get_prompt_tokens(&self) -> u64 {
+        self.prompt_tokens
+    }
+
+    pub fn get_completion_tokens(&self) -> u64 {
+        self.completion_tokens
+    }
+
+    pub fn get_total_tokens(&self) -> u64 {
+        self.total_tokens
+    }
+
+    /// Prompt tokens served from the cache, part of `prompt_tokens`
+    pub fn get_cached_tokens(&self) -> u64 {
+        self.cached_tokens
+    }
+
+    /// Hidden reasoning tokens, part of `completion_tokens`
+    pub fn get_reasoning_tokens(&self) -> u64 {
+        self.reasoning_tokens
+    }
+
+    /// Audio input tokens, part of `prompt_tokens`
+    pub fn get_prompt_audio_tokens(&self) -> u64 {
+        self.prompt_audio_tokens
+    }
+
+    /// Audio output tokens, part of `completion_tokens`
+    pub fn get_completion_audio_tokens(&self) -> u64 {
+        self.completion_audio_tokens
+    }
+
+    /// Price of these tokens with the prices `registry` has for `model`, the cached tokens at the cached price
+    pub fn cost(&self, registry: &Registry, model: &str) -> Option<Cost> {
+        registry.cost(model, self.prompt_tokens, self.cached_tokens, self.completion_tokens)
+    }
+}
+
+impl From<Usage> for TotalUsage {
+    fn from(usage: Usage) -> TotalUsage {
+        TotalUsage {
+            prompt_tokens: usage.prompt_tokens.into(),
+            completion_tokens: usage.completion_tokens.into(),
+            total_tokens: usage.total_tokens.into(),
+            cached_tokens: usage.get_cached_tokens().into(),
+            reasoning_tokens: usage.get_reasoning_tokens().into(),
+            prompt_audio_tokens: usage.get_prompt_audio_tokens().into(),
+            completion_audio_tokens: usage.get_completion_audio_tokens().into()
+        }
+    }
+}
+
+impl Add for TotalUsage {
+    type Output = TotalUsage;
+
+    fn add(self, other: TotalUsage) -> TotalUsage {
+        TotalUsage {
+            prompt_tokens: self.prompt_tokens + other.prompt_tokens,
+            completion_tokens: self.completion_tokens + other.completion_tokens,
+            total_tokens: self.total_tokens + other.total_tokens,
+            cached_tokens: self.cached_tokens + other.cached_tokens,
+            reasoning_tokens: self.reasoning_tokens + other.reasoning_tokens,
+            prompt_audio_tokens: self.prompt_audio_tokens + other.prompt_audio_tokens,
+            completion_audio_tokens: self.completion_audio_tokens + other.completion_audio_tokens
+        }
+    }
+}
+
+impl Add<Usage> for TotalUsage {
+    type Output = TotalUsage;
+
+    fn add(self, other: Usage) -> TotalUsage {
+        self + TotalUsage::from(other)
+    }
+}
+
+impl Add for Usage {
+    type Output = TotalUsage;
+
+    fn add(self, other: Usage) -> TotalUsage {
+        TotalUsage::from(self) + other
+    }
+}
+
+impl AddAssign for TotalUsage {
+    fn add_assign(&mut self, other: TotalUsage) {
+        *self = *self + other;
+    }
+}
+
+impl AddAssign<Usage> for TotalUsage {
+    fn add_assign(&mut self, other: Usage) {
+        *self = *self + other;
+    }
+}
+
+impl Sum for TotalUsage {
+    fn sum<I: Iterator<Item = TotalUsage>>(iter: I) -> TotalUsage {
+        iter.fold(TotalUsage::default(), Add::add)
+    }
+}
+
+impl<'a> Sum<&'a TotalUsage> for TotalUsage {
+    fn sum<I: Iterator<Item = &'a TotalUsage>>(iter: I) -> TotalUsage {
+        iter.copied().sum()
+    }
+}
+
+impl Sum<Usage> for TotalUsage {
+    fn sum<I: Iterator<Item = Usage>>(iter: I) -> TotalUsage {
+        iter.fold(TotalUsage::default(), Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Usage> for TotalUsage {
+    fn sum<I: Iterator<Item = &'a Usage>>(iter: I) -> TotalUsage {
+        iter.copied().sum()
+    }
+}
+
+/// Usage summed by the model that answered
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct UsageByModel {
+    models: HashMap<String, TotalUsage>
+}
+
+/// Key of responses that don't name their model
+pub const UNKNOWN_MODEL: &str = "unknown";
+
+impl UsageByModel {
+    pub fn new() -> UsageByModel {
+        UsageByModel::default()
+    }
+
+    /// Count the usage of `resp` under its model, or [`UNKNOWN_MODEL`]
+    pub fn add<Sentence>(&mut self, resp: &Resp<Sentence>) {
+        let model = resp.get_model().map(|m| m.as_str()).unwrap_or(UNKNOWN_MODEL);
+        self.add_usage(model, *resp.get_usage());
+    }
+
+    pub fn add_usage(&mut self, model: &str, usage: Usage) {
+        *self.models.entry(model.to_string()).or_default() += usage;
+    }
+
+    pub fn get(&self, model: &str) -> Option<&TotalUsage> {
+        self.models.get(model)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &TotalUsage)> {
+        self.models.iter()
+    }
+
+    /// All models together
+    pub fn total(&self) -> TotalUsage {
+        self.models.values().sum()
+    }
+
+    /// Price of everything, None if `registry` misses the prices of one of the models
+    pub fn cost(&self, registry: &Registry) -> Option<Cost> {
+        self.models.iter().try_fold(Cost::default(), |total, (model, usage)| Some(total + usage.cost(registry, model)?))
+    }
+}
+
+#[cfg(test)]
+mod usage_tests {
+    use super::*;
+
+    #[test]
+    fn test_de_usage_details() {
+        let usage: Usage = serde_json::from_str("{\"prompt_tokens\":120000,\"completion_tokens\":900,\"total_tokens\":120900,\"prompt_tokens_details\":{\"cached_tokens\":100000,\"audio_tokens\":null},\"completion_tokens_details\":{\"reasoning_tokens\":640,\"audio_tokens\":0,\"accepted_prediction_tokens\":0}}").unwrap();
+        assert_eq!((usage.get_prompt_tokens(), usage.get_total_tokens()), (120_000, 120_900));
+        assert_eq!((usage.get_cached_tokens(), usage.get_prompt_audio_tokens(), usage.get_reasoning_tokens()), (100_000, 0, 640));
+        let plain: Usage = serde_json::from_str("{\"prompt_tokens\":1,\"completion_tokens\":1,\"total_tokens\":2}").unwrap();
+        assert_eq!(plain, Usage::new(1, 1));
+        assert_eq!(serde_json::to_string(&plain).unwrap(), "{\"prompt_tokens\":1,\"completion_tokens\":1,\"total_tokens\":2}");
+
+        let sum = usage + plain;
+        assert_eq!((sum.get_prompt_tokens(), sum.get_cached_tokens(), sum.get_reasoning_tokens()), (120_001, 100_000, 640));
+        let mut acc = TotalUsage::default();
+        acc += plain;
+        acc += plain;
+        assert_eq!(acc, [plain, plain].iter().sum());
+        assert_eq!(acc.get_total_tokens(), 4);
+    }
+
+    #[test]
+    fn test_usage_by_model() {
+        let registry = Registry::builtin();
+        let mut by_model = UsageByModel::new();
+        by_model.add_usage("gpt-4o", Usage::new(1000, 500));
+        by_model.add_usage("gpt-4o", Usage::new(1000, 500));
+        by_model.add_usage("gpt-4o-mini", Usage::new(1000, 0));
+        assert_eq!(by_model.get("gpt-4o"), Some(&(Usage::new(1000, 500) + Usage::new(1000, 500))));
+        assert_eq!(by_model.get("gpt-4o").unwrap().get_total_tokens(), 3000);
+        assert_eq!(by_model.total().get_total_tokens(), 4000);
+        let cost = by_model.cost(registry).unwrap();
+        assert!((cost.total() - (0.005 + 0.01 + 0.00015)).abs() < 1e-12);
+        by_model.add_usage("my-local-model", Usage::new(1, 1));
+        assert_eq!(by_model.cost(registry), None);
+    }
+
+    #[test]
+    fn test_usage_past_u32() {
+        let details: Usage = serde_json::from_str("{\"prompt_tokens\":4000000000,\"completion_tokens\":0,\"total_tokens\":4000000000,\"prompt_tokens_details\":{\"cached_tokens\":4000000000}}").unwrap();
+        let sum: TotalUsage = [details, details, Usage::new(1, 1)].iter().sum();
+        assert_eq!((sum.get_prompt_tokens(), sum.get_completion_tokens(), sum.get_total_tokens()), (8_000_000_001, 1, 8_000_000_002));
+        assert_eq!(sum.get_cached_tokens(), 8_000_000_000);
+
+        let huge = Usage::new(u32::MAX - 10, 10);
+        let mut by_model = UsageByModel::new();
+        by_model.add_usage("gpt-4o", huge);
+        by_model.add_usage("gpt-4o", huge);
+        by_model.add_usage("gpt-4o-mini", huge);
+        assert_eq!(by_model.get("gpt-4o").unwrap().get_prompt_tokens(), 2 * (u32::MAX as u64 - 10));
+        assert_eq!(by_model.total().get_total_tokens(), 3 * u32::MAX as u64);
+        let input = by_model.get("gpt-4o").unwrap().cost(Registry::builtin(), "gpt-4o").unwrap().get_input();
+        assert!((input - 2.0 * (u32::MAX - 10) as f64 / 1000.0 * 0.0025).abs() < 1e-6);
+    }
+}
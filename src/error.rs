@@ -0,0 +1,336 @@
+use std::fmt;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use reqwest::header::RETRY_AFTER;
+use serde::Deserialize;
+use serde::Deserializer;
+
+use crate::datas::request::Warning;
+use crate::netreq::meta::REQUEST_ID;
+
+/// Everything that can go wrong in this crate
+#[derive(Debug)]
+pub enum Error {
+    /// A parameter was rejected before anything was sent, e.g. temperature out of range
+    Validation { field: &'static str, reason: String },
+    /// The request never got a complete response
+    Transport(TransportError),
+    /// Non-success status whose body is not an OpenAI error object
+    Status { status: StatusCode, headers: Box<HeaderMap>, body: String },
+    /// Error object returned by the API \
+    /// `headers` is empty when the error arrived in the middle of a stream
+    Api { status: StatusCode, headers: Box<HeaderMap>, error: ApiError },
+    /// Response (or stream chunk) is not what we expected
+    Decode(String),
+    /// Reading or writing a local file failed
+    Io(std::io::Error),
+    /// JSON does not match the schema it was checked against, `path` is like `$.items[2].name`
+    SchemaMismatch { path: String, reason: String },
+    /// The model declined to answer in the requested format, with its explanation
+    Refusal(String),
+    /// Every parameter [`BodyBuilder::build`](crate::datas::builder::BodyBuilder::build) rejected, each a `Validation`,
+    /// with the warnings found along the way
+    Invalid { errors: Vec<Error>, warnings: Vec<Warning> }
+}
+
+impl Error {
+    pub(crate) fn validation(field: &'static str, reason: impl Into<String>) -> Error {
+        Error::Validation { field, reason: reason.into() }
+    }
+
+    /// Build the error for a non-success response from its status, headers and raw body
+    pub fn from_response(status: StatusCode, headers: HeaderMap, body: &[u8]) -> Error {
+        match serde_json::from_slice::<ApiErrorBody>(body) {
+            Ok(gets) => Error::Api { status, headers: Box::new(headers), error: gets.error },
+            Err(_) => Error::Status { status, headers: Box::new(headers), body: String::from_utf8_lossy(body).into_owned() }
+        }
+    }
+
+    /// HTTP status of the failed response, if there was one
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Status { status, .. } | Error::Api { status, .. } => Some(*status),
+            _ => None
+        }
+    }
+
+    /// Headers of the failed response, if there was one
+    pub fn headers(&self) -> Option<&HeaderMap> {
+        match self {
+            Error::Status { headers, .. } | Error::Api { headers, .. } => Some(headers),
+            _ => None
+        }
+    }
+
+    /// `x-request-id` of the failed response, quote it when asking the provider for help
+    pub fn request_id(&self) -> Option<&str> {
+        self.headers()?.get(REQUEST_ID)?.to_str().ok()
+    }
+
+    /// How long the server asked us to wait, from `retry-after-ms`, or `retry-after` in seconds or as an HTTP date \
+    /// A date already past gives zero.
+    pub fn retry_after(&self) -> Option<Duration> {
+        let headers = self.headers()?;
+        let get = |name| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+        if let Some(ms) = get(RETRY_AFTER_MS).and_then(|v| v.parse::<f64>().ok()) {
+            return Duration::try_from_secs_f64(ms / 1000.0).ok();
+        }
+        let after = get(RETRY_AFTER.as_str())?;
+        match after.parse::<f64>() {
+            Ok(secs) => Duration::try_from_secs_f64(secs).ok(),
+            Err(_) => httpdate::parse_http_date(after).ok().map(|at| at.duration_since(SystemTime::now()).unwrap_or_default())
+        }
+    }
+
+    /// Error object returned by the API, if any
+    pub fn api_error(&self) -> Option<&ApiError> {
+        match self {
+            Error::Api { error, .. } => Some(error),
+            _ => None
+        }
+    }
+
+    pub fn is_unauthorized(&self) -> bool {
+        self.status() == Some(StatusCode::UNAUTHORIZED)
+    }
+
+    /// Too many requests, but not an exhausted quota (which won't go away by waiting)
+    pub fn is_rate_limited(&self) -> bool {
+        self.status() == Some(StatusCode::TOO_MANY_REQUESTS) && !self.has_code("insufficient_quota")
+    }
+
+    pub fn is_quota_exceeded(&self) -> bool {
+        self.has_code("insufficient_quota")
+    }
+
+    pub fn is_context_length_exceeded(&self) -> bool {
+        self.has_code("context_length_exceeded")
+    }
+
+    fn has_code(&self, code: &str) -> bool {
+        self.api_error().and_then(|e| e.get_code()) == Some(code)
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Validation { field, reason } => write!(f, "invalid {}: {}", field, reason),
+            Error::Transport(x) => write!(f, "server not response: {}", x),
+            Error::Status { status, body, .. } => write!(f, "error code: {}: {}", status, body),
+            Error::Api { status, error, .. } => write!(f, "error code: {}: {}", status, error),
+            Error::Decode(x) => write!(f, "resp parse error: {}", x),
+            Error::Io(x) => write!(f, "io error: {}", x),
+            Error::SchemaMismatch { path, reason } => write!(f, "schema mismatch at {}: {}", path, reason),
+            Error::Refusal(x) => write!(f, "model refused: {}", x),
+            Error::Invalid { errors, .. } => write!(f, "invalid body: {}", errors.iter().map(Error::to_string).collect::<Vec<String>>().join("; "))
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Transport(x) => Some(x),
+            Error::Io(x) => Some(x),
+            _ => None
+        }
+    }
+}
+
+impl From<TransportError> for Error {
+    fn from(x: TransportError) -> Self {
+        Error::Transport(x)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(x: reqwest::Error) -> Self {
+        match x.is_decode() {
+            true => Error::Decode(x.to_string()),
+            false => Error::Transport(x.into())
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(x: std::io::Error) -> Self {
+        Error::Io(x)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(x: serde_json::Error) -> Self {
+        Error::Decode(x.to_string())
+    }
+}
+
+/// Millisecond variant of `retry-after` sent by OpenAI
+const RETRY_AFTER_MS: &str = "retry-after-ms";
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransportErrorKind {
+    /// Could not connect to the server
+    Connect,
+    /// Request or response took too long
+    Timeout,
+    /// Connection dropped while reading the body
+    Body,
+    Other
+}
+
+#[derive(Debug)]
+pub struct TransportError {
+    kind: TransportErrorKind,
+    source: Box<dyn std::error::Error + Send + Sync>
+}
+
+impl TransportError {
+    pub fn new(kind: TransportErrorKind, source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> TransportError {
+        TransportError { kind, source: source.into() }
+    }
+
+    pub fn get_kind(&self) -> TransportErrorKind {
+        self.kind
+    }
+}
+
+impl fmt::Display for TransportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.source.fmt(f)
+    }
+}
+
+impl std::error::Error for TransportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+impl From<reqwest::Error> for TransportError {
+    fn from(x: reqwest::Error) -> Self {
+        let kind = if x.is_timeout() {
+            TransportErrorKind::Timeout
+        } else if x.is_connect() {
+            TransportErrorKind::Connect
+        } else if x.is_body() {
+            TransportErrorKind::Body
+        } else {
+            TransportErrorKind::Other
+        };
+        TransportError::new(kind, x)
+    }
+}
+
+/// Error object of the API, sent as `{"error": {...}}`
+#[derive(Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct ApiError {
+    message: String,
+    #[serde(rename = "type", default)]
+    kind: Option<String>,
+    #[serde(default)]
+    param: Option<String>,
+    /// Some compatible servers send a number here
+    #[serde(default, deserialize_with = "string_or_number")]
+    code: Option<String>
+}
+
+impl ApiError {
+    pub fn get_message(&self) -> &str {
+        &self.message
+    }
+
+    /// `type` field, e.g. "invalid_request_error"
+    pub fn get_kind(&self) -> Option<&str> {
+        self.kind.as_deref()
+    }
+
+    pub fn get_param(&self) -> Option<&str> {
+        self.param.as_deref()
+    }
+
+    /// e.g. "context_length_exceeded", "rate_limit_exceeded"
+    pub fn get_code(&self) -> Option<&str> {
+        self.code.as_deref()
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.code {
+            Some(code) => write!(f, "{} ({})", self.message, code),
+            None => write!(f, "{}", self.message)
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ApiErrorBody {
+    pub(crate) error: ApiError
+}
+
+fn string_or_number<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<String>, D::Error> {
+    match Option::<serde_json::Value>::deserialize(deserializer)? {
+        Some(serde_json::Value::String(x)) => Ok(Some(x)),
+        Some(serde_json::Value::Null) | None => Ok(None),
+        Some(other) => Ok(Some(other.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod error_tests {
+    use super::*;
+
+    #[test]
+    fn test_api_error_from_response() {
+        let body = b"{\"error\":{\"message\":\"This model's maximum context length is 4097 tokens.\",\"type\":\"invalid_request_error\",\"param\":\"messages\",\"code\":\"context_length_exceeded\"}}";
+        let err = Error::from_response(StatusCode::BAD_REQUEST, HeaderMap::new(), body);
+        let api = err.api_error().unwrap();
+        assert_eq!(api.get_kind(), Some("invalid_request_error"));
+        assert_eq!(api.get_param(), Some("messages"));
+        assert!(err.is_context_length_exceeded());
+        assert!(!err.is_rate_limited());
+    }
+
+    #[test]
+    fn test_rate_limit_and_quota() {
+        let limited = Error::from_response(StatusCode::TOO_MANY_REQUESTS, HeaderMap::new(), b"{\"error\":{\"message\":\"Rate limit reached\",\"type\":\"requests\",\"param\":null,\"code\":\"rate_limit_exceeded\"}}");
+        let quota = Error::from_response(StatusCode::TOO_MANY_REQUESTS, HeaderMap::new(), b"{\"error\":{\"message\":\"You exceeded your current quota\",\"type\":\"insufficient_quota\",\"code\":\"insufficient_quota\"}}");
+        assert!(limited.is_rate_limited());
+        assert!(!quota.is_rate_limited());
+        assert!(quota.is_quota_exceeded());
+    }
+
+    #[test]
+    fn test_status_without_error_body() {
+        let err = Error::from_response(StatusCode::BAD_GATEWAY, HeaderMap::new(), b"<html>bad gateway</html>");
+        assert!(matches!(err, Error::Status { status: StatusCode::BAD_GATEWAY, .. }));
+        assert_eq!(err.retry_after(), None);
+        let numeric = Error::from_response(StatusCode::BAD_REQUEST, HeaderMap::new(), b"{\"error\":{\"message\":\"bad\",\"code\":400}}");
+        assert_eq!(numeric.api_error().unwrap().get_code(), Some("400"));
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, "2".parse().unwrap());
+        let err = Error::from_response(StatusCode::SERVICE_UNAVAILABLE, headers.clone(), b"");
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(2)));
+        headers.insert(RETRY_AFTER_MS, "150".parse().unwrap());
+        let err = Error::from_response(StatusCode::SERVICE_UNAVAILABLE, headers, b"");
+        assert_eq!(err.retry_after(), Some(Duration::from_millis(150)));
+
+        let mut headers = HeaderMap::new();
+        let at = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(120));
+        headers.insert(RETRY_AFTER, at.parse().unwrap());
+        let wait = Error::from_response(StatusCode::TOO_MANY_REQUESTS, headers.clone(), b"").retry_after().unwrap();
+        assert!(wait > Duration::from_secs(110) && wait <= Duration::from_secs(120));
+        headers.insert(RETRY_AFTER, "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap());
+        assert_eq!(Error::from_response(StatusCode::TOO_MANY_REQUESTS, headers.clone(), b"").retry_after(), Some(Duration::ZERO));
+        headers.insert(RETRY_AFTER, "soon".parse().unwrap());
+        assert_eq!(Error::from_response(StatusCode::TOO_MANY_REQUESTS, headers, b"").retry_after(), None);
+    }
+}
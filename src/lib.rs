@@ -3,18 +3,18 @@
 //! 2. A CLI interface, which can ask some questions and get answers ONE BY ONE
 //! 3. Store questions in a file with special formats and be able to use these questions to call the API
 //! 4. Using methods 3, can insert an answer into a specified location in an article with a specific format to produce a complete article
-//!     It should seem like this:
-//!     - Article
+//!    It should seem like this:
+//!    - Article
 //! 
-//!     ```
-//!     As we all know, Earth is a {{earth-be-like}}
-//!     ```
+//!    ```text
+//!    As we all know, Earth is a {{earth-be-like}}
+//!    ```
 //! 
-//!     - Answers
+//!    - Answers
 //! 
-//!     ```
-//!     earth-be-like = "Answers from questions: 'What is earth'"
-//!     ```
+//!    ```text
+//!    earth-be-like = "Answers from questions: 'What is earth'"
+//!    ```
 //! 5. log system(record raw questions, curl format api call, raw response and so on)
 //! 
 //! # Constructions
@@ -23,8 +23,14 @@
 //! - Network Requests, request data should be related to a response data(using trait and type) - netreq
 //! - Data formats(Display trait(display), Default trait, option trait(Just use Option), required trait(Not Option), support correct serialize and deserialize methods(Generate right output for request body and read data), API Callers can just use create and edit(**use provided methods**) funcs without worring about incorrect attributes in request(limited and auto check). Users just ask questions and get answers. - formats
 
-mod datas;
-mod netreq;
+extern crate self as xtgptr;
+
+pub mod credentials;
+pub mod datas;
+pub mod error;
+pub mod netreq;
+pub mod schema;
+pub mod secret;
 
 
 
@@ -0,0 +1,371 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Arc;
+
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use futures::future::join_all;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::datas::request::Body;
+use crate::datas::request::Message;
+use crate::datas::response::Resp;
+use crate::datas::tool::Tool;
+use crate::datas::tool::ToolCall;
+use crate::error::Error;
+use crate::netreq::client::Client;
+use crate::netreq::perform::GenHeaders;
+use crate::schema;
+use crate::schema::JsonSchema;
+
+type E = Result<(), Error>;
+
+/// What a tool hands back to the model, an `Err` is sent as `error: <message>` so the model can react to it
+pub type ToolOutput = Result<String, String>;
+
+type Handler = Arc<dyn Fn(Value) -> BoxFuture<'static, ToolOutput> + Send + Sync>;
+
+type Veto = Arc<dyn Fn(&ToolCall) -> bool + Send + Sync>;
+
+/// Tools the model may call, with the async functions that run them
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<Tool>,
+    handlers: HashMap<String, Handler>
+}
+
+impl ToolRegistry {
+    pub fn new() -> ToolRegistry {
+        ToolRegistry::default()
+    }
+
+    /// Register `handler` under `name`, it gets the arguments parsed as JSON \
+    /// `parameters` is the JSON schema of the arguments, as in [`Tool::function`]
+    pub fn register<F, Fut>(&mut self, name: &str, description: &str, parameters: Value, handler: F) -> E
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ToolOutput> + Send + 'static
+    {
+        self.insert(Tool::function(name, description, parameters)?, Arc::new(move |args| handler(args).boxed()))
+    }
+
+    /// Register `handler` under `name` with the strict schema of `T` \
+    /// Arguments not matching the schema are answered with the mismatch, `handler` is not called.
+    pub fn register_typed<T, F, Fut>(&mut self, name: &str, description: &str, handler: F) -> E
+    where
+        T: JsonSchema + DeserializeOwned,
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ToolOutput> + Send + 'static
+    {
+        self.insert(Tool::typed::<T>(name, description)?, Arc::new(move |args| match schema::from_value::<T>(args) {
+            Ok(args) => handler(args).boxed(),
+            Err(e) => futures::future::ready(Err(e.to_string())).boxed()
+        }))
+    }
+
+    fn insert(&mut self, tool: Tool, handler: Handler) -> E {
+        if self.handlers.contains_key(tool.get_name()) {
+            return Err(Error::validation("tools", format!("tool {} is registered twice", tool.get_name())));
+        }
+        self.handlers.insert(tool.get_name().to_string(), handler);
+        self.tools.push(tool);
+        Ok(())
+    }
+
+    pub fn get_tools(&self) -> &[Tool] {
+        &self.tools
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+
+    /// Run the tool asked for by `call`, unknown tools, bad arguments and failures end up in the text
+    pub async fn call(&self, call: &ToolCall) -> String {
+        let name = call.get_function().get_name();
+        let handler = match self.handlers.get(name) {
+            Some(handler) => handler.clone(),
+            None => return format!("error: unknown tool {}", name)
+        };
+        let arguments = match call.get_function().parse_arguments() {
+            Ok(arguments) => arguments,
+            Err(e) => return format!("error: invalid arguments: {}", e)
+        };
+        match handler(arguments).await {
+            Ok(output) => output,
+            Err(e) => format!("error: {}", e)
+        }
+    }
+}
+
+impl fmt::Debug for ToolRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ToolRegistry").field("tools", &self.tools).finish()
+    }
+}
+
+/// Why a [`Run`] ended
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Stop {
+    /// The model answered without calling a tool
+    Answered,
+    /// The model still called tools when the iteration limit was reached, those calls were not run
+    MaxIterations,
+    /// The veto rejected this call, no call of its batch was run
+    Vetoed(ToolCall)
+}
+
+/// Result of [`Agent::run`]
+#[derive(Debug)]
+pub struct Run {
+    body: Body<String>,
+    resp: Resp<String>,
+    iterations: usize,
+    stop: Stop
+}
+
+impl Run {
+    /// The request as last sent, its messages are the whole conversation but the last response
+    pub fn get_body(&self) -> &Body<String> {
+        &self.body
+    }
+
+    pub fn get_messages(&self) -> &Vec<Message<String>> {
+        self.body.get_messages()
+    }
+
+    /// Last response of the model
+    pub fn get_resp(&self) -> &Resp<String> {
+        &self.resp
+    }
+
+    /// Text of the last response
+    pub fn get_answer(&self) -> Option<&str> {
+        self.resp.get_first_content().map(String::as_str)
+    }
+
+    /// Requests sent
+    pub fn get_iterations(&self) -> usize {
+        self.iterations
+    }
+
+    pub fn get_stop(&self) -> &Stop {
+        &self.stop
+    }
+
+    pub fn into_body(self) -> Body<String> {
+        self.body
+    }
+}
+
+/// Sends a body, runs the tools the model calls, answers with `Tool` messages and sends again,
+/// until the model answers, the iteration limit is reached or the veto says no \
+/// Calls of one response run concurrently.
+pub struct Agent<Auth> {
+    client: Client<Auth>,
+    tools: ToolRegistry,
+    max_iterations: usize,
+    veto: Option<Veto>
+}
+
+impl<Auth: GenHeaders + Send + Sync> Agent<Auth> {
+    /// At most 10 requests per run by default
+    pub fn new(client: Client<Auth>, tools: ToolRegistry) -> Agent<Auth> {
+        Agent { client, tools, max_iterations: 10, veto: None }
+    }
+
+    /// Requests one run may send, at least 1
+    pub fn set_max_iterations(&mut self, max_iterations: usize) -> E {
+        if max_iterations == 0 {
+            return Err(Error::validation("max_iterations", "max_iterations must be at least 1"));
+        }
+        self.max_iterations = max_iterations;
+        Ok(())
+    }
+
+    /// `veto` sees every call before it runs, returning true stops the run with [`Stop::Vetoed`]
+    pub fn set_veto(&mut self, veto: impl Fn(&ToolCall) -> bool + Send + Sync + 'static) {
+        self.veto = Some(Arc::new(veto));
+    }
+
+    pub fn get_client(&self) -> &Client<Auth> {
+        &self.client
+    }
+
+    pub fn get_tools(&self) -> &ToolRegistry {
+        &self.tools
+    }
+
+    pub fn get_max_iterations(&self) -> usize {
+        self.max_iterations
+    }
+
+    /// Registered tools not declared in `body` yet are added to it
+    pub async fn run(&self, mut body: Body<String>) -> Result<Run, Error> {
+        for tool in self.tools.get_tools() {
+            if !body.get_tools().iter().any(|t| t.get_name() == tool.get_name()) {
+                body.add_tool(tool.clone())?;
+            }
+        }
+        let mut iterations = 0;
+        loop {
+            iterations += 1;
+            let resp = self.client.chat(&body).await?;
+            let message = match resp.get_choices().first() {
+                Some(choice) => choice.get_message(),
+                None => return Ok(Run { body, resp, iterations, stop: Stop::Answered })
+            };
+            let calls = message.get_tool_calls().to_vec();
+            if calls.is_empty() {
+                return Ok(Run { body, resp, iterations, stop: Stop::Answered });
+            }
+            if iterations >= self.max_iterations {
+                return Ok(Run { body, resp, iterations, stop: Stop::MaxIterations });
+            }
+            if let Some(veto) = &self.veto {
+                if let Some(call) = calls.iter().find(|call| veto(call)) {
+                    let stop = Stop::Vetoed(call.clone());
+                    return Ok(Run { body, resp, iterations, stop });
+                }
+            }
+            log::debug!("running {} tool call(s), iteration {}", calls.len(), iterations);
+            let outputs = join_all(calls.iter().map(|call| self.tools.call(call))).await;
+            body.add_message(Message::tool_calls(message.get_content().as_text().cloned(), calls.clone()));
+            for (call, output) in calls.iter().zip(outputs) {
+                body.add_message(Message::tool(call.get_id(), output));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod agent_tests {
+    use serde_json::json;
+
+    use crate::datas::request::ChatLogin;
+    use crate::datas::request::Roles;
+    use crate::netreq::test_server::completion;
+    use crate::netreq::transport::MemoryTransport;
+    use crate::netreq::transport::Transport;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn calling(calls: &[(&str, &str, &str)]) -> String {
+        let calls: Vec<Value> = calls.iter()
+            .map(|(id, name, arguments)| json!({"id": id, "type": "function", "function": {"name": name, "arguments": arguments}}))
+            .collect();
+        json!({
+            "id": "chatcmpl-1", "object": "chat.completion", "created": 1677652288,
+            "choices": [{"index": 0, "message": {"role": "assistant", "content": null, "tool_calls": calls}, "finish_reason": "tool_calls"}],
+            "usage": {"prompt_tokens": 9, "completion_tokens": 12, "total_tokens": 21}
+        }).to_string()
+    }
+
+    fn agent(transport: &Arc<MemoryTransport>) -> Agent<ChatLogin<&'static str>> {
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let client = Client::builder(token).shared_transport(transport.clone() as Arc<dyn Transport>).build().unwrap();
+        let mut tools = ToolRegistry::new();
+        let schema = json!({"type": "object", "properties": {"city": {"type": "string"}}, "required": ["city"]});
+        tools.register("get_weather", "Current weather of a city", schema, |args: Value| async move {
+            match args["city"].as_str() {
+                Some("Paris") => Ok(String::from("sunny")),
+                Some("Oslo") => Ok(String::from("rainy")),
+                _ => Err(String::from("no such city"))
+            }
+        }).unwrap();
+        Agent::new(client, tools)
+    }
+
+    fn question() -> Body<String> {
+        let mut body = Body::<String>::default();
+        body.add_message(Message::new(Roles::User, String::from("Weather in Paris and Oslo?")));
+        body
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut tools = ToolRegistry::new();
+        tools.register("echo", "", json!({"type": "object"}), |args: Value| async move { Ok(args.to_string()) }).unwrap();
+        assert!(tools.register("echo", "", json!({"type": "object"}), |_| async { Ok(String::new()) }).is_err());
+        assert!(tools.register("bad name", "", json!({"type": "object"}), |_| async { Ok(String::new()) }).is_err());
+        assert_eq!(tools.len(), 1);
+        assert_eq!(aw!(tools.call(&ToolCall::new("1", "echo", "{\"a\":1}"))), "{\"a\":1}");
+        assert_eq!(aw!(tools.call(&ToolCall::new("2", "nope", "{}"))), "error: unknown tool nope");
+        assert!(aw!(tools.call(&ToolCall::new("3", "echo", "{\"a\":"))).starts_with("error: invalid arguments"));
+    }
+
+    #[derive(serde::Deserialize, JsonSchema)]
+    struct Add {
+        a: i64,
+        b: i64
+    }
+
+    #[test]
+    fn test_register_typed() {
+        let mut tools = ToolRegistry::new();
+        tools.register_typed("add", "Add two numbers", |args: Add| async move { Ok((args.a + args.b).to_string()) }).unwrap();
+        assert_eq!(tools.get_tools()[0].get_function().get_strict(), Some(true));
+        assert_eq!(aw!(tools.call(&ToolCall::new("1", "add", "{\"a\":1,\"b\":2}"))), "3");
+        assert_eq!(aw!(tools.call(&ToolCall::new("2", "add", "{\"a\":1}"))), "error: schema mismatch at $: missing property b");
+    }
+
+    #[test]
+    fn test_run() {
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_reply(200, calling(&[("call_1", "get_weather", "{\"city\":\"Paris\"}"), ("call_2", "get_weather", "{\"city\":\"Oslo\"}")]))
+            .push_reply(200, calling(&[("call_3", "get_weather", "{\"city\":\"Atlantis\"}")]))
+            .push_reply(200, completion("Sunny in Paris, rainy in Oslo"));
+        let run = aw!(agent(&transport).run(question())).unwrap();
+        assert_eq!(run.get_stop(), &Stop::Answered);
+        assert_eq!(run.get_iterations(), 3);
+        assert_eq!(run.get_answer(), Some("Sunny in Paris, rainy in Oslo"));
+
+        let messages = run.get_messages();
+        assert_eq!(messages.len(), 6);
+        assert_eq!(messages[1].get_tool_calls().len(), 2);
+        assert_eq!((messages[2].get_tool_call_id(), messages[2].get_content().to_text().as_str()), (Some("call_1"), "sunny"));
+        assert_eq!((messages[3].get_tool_call_id(), messages[3].get_content().to_text().as_str()), (Some("call_2"), "rainy"));
+        assert_eq!(messages[5].get_content().to_text(), "error: no such city");
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 3);
+        let last: Value = serde_json::from_slice(requests[2].get_body()).unwrap();
+        assert_eq!(last["tools"][0]["function"]["name"], "get_weather");
+        assert_eq!(last["messages"][1]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(last["messages"][2]["role"], "tool");
+    }
+
+    #[test]
+    fn test_stops() {
+        let transport = Arc::new(MemoryTransport::new());
+        let looping = calling(&[("call_1", "get_weather", "{\"city\":\"Paris\"}")]);
+        transport.push_reply(200, looping.clone()).push_reply(200, looping.clone());
+        let mut agent = agent(&transport);
+        agent.set_max_iterations(2).unwrap();
+        assert!(agent.set_max_iterations(0).is_err());
+        let run = aw!(agent.run(question())).unwrap();
+        assert_eq!((run.get_stop(), run.get_iterations()), (&Stop::MaxIterations, 2));
+        // the pending calls of the last response were not answered
+        assert_eq!(run.get_messages().len(), 3);
+
+        transport.push_reply(200, looping);
+        agent.set_veto(|call| call.get_function().get_arguments().contains("Paris"));
+        let run = aw!(agent.run(question())).unwrap();
+        assert_eq!(run.get_stop(), &Stop::Vetoed(ToolCall::new("call_1", "get_weather", "{\"city\":\"Paris\"}")));
+        assert_eq!((run.get_iterations(), run.get_messages().len()), (1, 1));
+        assert_eq!(transport.remaining(), 0);
+    }
+}
@@ -0,0 +1,277 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::HeaderValue;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Map;
+use serde_json::Value;
+
+use crate::error::Error;
+use crate::error::TransportError;
+use crate::error::TransportErrorKind;
+use crate::netreq::transport::HttpRequest;
+use crate::netreq::transport::HttpResponse;
+use crate::netreq::transport::Transport;
+
+/// Fields of a request body that decide which recorded response answers it
+const MATCHED_FIELDS: [&str; 16] = [
+    "model",
+    "messages",
+    "temperature",
+    "top_p",
+    "n",
+    "stream",
+    "stop",
+    "max_tokens",
+    "presence_penalty",
+    "frequency_penalty",
+    "logit_bias",
+    "user",
+    "tools",
+    "tool_choice",
+    "parallel_tool_calls",
+    "response_format"
+];
+
+/// One recorded exchange, a line of the cassette file
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct Entry {
+    /// Path of the endpoint, without the base url
+    path: String,
+    /// Normalized request body, see [`normalize`]
+    request: Value,
+    status: u16,
+    headers: Vec<(String, String)>,
+    /// Response body as JSON, or as a string when it isn't (e.g. server-sent events)
+    response: Value
+}
+
+impl Entry {
+    pub fn get_path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn get_request(&self) -> &Value {
+        &self.request
+    }
+
+    pub fn get_status(&self) -> u16 {
+        self.status
+    }
+
+    pub fn get_headers(&self) -> &Vec<(String, String)> {
+        &self.headers
+    }
+
+    pub fn get_response(&self) -> &Value {
+        &self.response
+    }
+
+    fn key(&self) -> String {
+        format!("{} {}", self.path, self.request)
+    }
+
+    fn to_response(&self) -> Result<HttpResponse, TransportError> {
+        let status = StatusCode::from_u16(self.status).map_err(|e| TransportError::new(TransportErrorKind::Other, e))?;
+        let mut headers = HeaderMap::new();
+        for (k, v) in &self.headers {
+            let name = HeaderName::from_bytes(k.as_bytes()).map_err(|e| TransportError::new(TransportErrorKind::Other, e))?;
+            let value = HeaderValue::from_str(v).map_err(|e| TransportError::new(TransportErrorKind::Other, e))?;
+            headers.append(name, value);
+        }
+        let body = match &self.response {
+            Value::String(raw) => raw.clone().into_bytes(),
+            json => json.to_string().into_bytes()
+        };
+        Ok(HttpResponse::full(status, headers, body))
+    }
+}
+
+/// Keep only the fields in [`MATCHED_FIELDS`] that are set, so requests differing in
+/// key order, unset options or unrelated fields are the same request
+pub fn normalize(body: &Value) -> Value {
+    let mut kept = Map::new();
+    if let Value::Object(fields) = body {
+        for name in MATCHED_FIELDS {
+            match fields.get(name) {
+                None | Some(Value::Null) => {},
+                Some(value) => {
+                    kept.insert(name.to_string(), value.clone());
+                }
+            }
+        }
+    }
+    Value::Object(kept)
+}
+
+fn path_of(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(url) => url.path().to_string(),
+        Err(_) => url.to_string()
+    }
+}
+
+enum Mode {
+    Record { inner: Box<dyn Transport>, file: Mutex<File> },
+    /// Recorded entries by key, served in recording order, the last one repeats
+    Replay { entries: Mutex<HashMap<String, Vec<Entry>>> }
+}
+
+/// Transport recording every exchange to a JSONL file, or answering from such a file without network \
+/// Record once against the real API, commit the file, and replay it in tests:
+/// ```no_run
+/// # use xtgptr::netreq::cassette::Cassette;
+/// # use xtgptr::netreq::transport::ReqwestTransport;
+/// let recorder = Cassette::record("tests/cassettes/greeting.jsonl", ReqwestTransport::default()).unwrap();
+/// let player = Cassette::replay("tests/cassettes/greeting.jsonl").unwrap();
+/// ```
+/// Requests are matched on the endpoint path and the [`normalize`]d body, credentials are never written.
+pub struct Cassette {
+    mode: Mode
+}
+
+impl Cassette {
+    /// Send through `inner` and write every exchange to `path`, which is truncated first
+    pub fn record(path: impl AsRef<Path>, inner: impl Transport + 'static) -> Result<Cassette, Error> {
+        let file = File::create(path)?;
+        Ok(Cassette { mode: Mode::Record { inner: Box::new(inner), file: Mutex::new(file) } })
+    }
+
+    /// Like [`record`](Cassette::record), adding to the end of `path`
+    pub fn append(path: impl AsRef<Path>, inner: impl Transport + 'static) -> Result<Cassette, Error> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Cassette { mode: Mode::Record { inner: Box::new(inner), file: Mutex::new(file) } })
+    }
+
+    /// Answer from the entries recorded in `path`, a request without entry fails
+    pub fn replay(path: impl AsRef<Path>) -> Result<Cassette, Error> {
+        let mut entries = Vec::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str::<Entry>(&line)?);
+            }
+        }
+        Ok(Cassette::from_entries(entries))
+    }
+
+    pub fn from_entries(entries: Vec<Entry>) -> Cassette {
+        let mut by_key: HashMap<String, Vec<Entry>> = HashMap::new();
+        for entry in entries {
+            by_key.entry(entry.key()).or_default().push(entry);
+        }
+        Cassette { mode: Mode::Replay { entries: Mutex::new(by_key) } }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        matches!(self.mode, Mode::Record { .. })
+    }
+}
+
+#[async_trait]
+impl Transport for Cassette {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        let path = path_of(request.get_url());
+        let normalized = normalize(&request.json());
+        match &self.mode {
+            Mode::Record { inner, file } => {
+                let response = inner.send(request).await?;
+                let (status, headers) = (response.get_status(), response.get_headers().clone());
+                let body = response.bytes().await?;
+                let entry = Entry {
+                    path,
+                    request: normalized,
+                    status: status.as_u16(),
+                    headers: headers.iter()
+                        .filter_map(|(k, v)| v.to_str().ok().map(|v| (k.to_string(), v.to_string())))
+                        .collect(),
+                    response: serde_json::from_slice(&body)
+                        .unwrap_or_else(|_| Value::String(String::from_utf8_lossy(&body).into_owned()))
+                };
+                let line = serde_json::to_string(&entry).map_err(|e| TransportError::new(TransportErrorKind::Other, e))?;
+                writeln!(file.lock().unwrap(), "{}", line).map_err(|e| TransportError::new(TransportErrorKind::Other, e))?;
+                Ok(HttpResponse::full(status, headers, body))
+            },
+            Mode::Replay { entries } => {
+                let key = format!("{} {}", path, normalized);
+                let mut entries = entries.lock().unwrap();
+                let entry = match entries.get_mut(&key) {
+                    Some(recorded) if recorded.len() > 1 => recorded.remove(0),
+                    Some(recorded) => recorded[0].clone(),
+                    None => return Err(TransportError::new(TransportErrorKind::Other, format!("no cassette entry for {}", key)))
+                };
+                entry.to_response()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod cassette_tests {
+    use serde_json::json;
+
+    use crate::datas::request::Body;
+    use crate::datas::request::ChatLogin;
+    use crate::datas::request::Message;
+    use crate::datas::request::Roles;
+    use crate::netreq::config::Config;
+    use crate::netreq::perform::AsyncPerform;
+    use crate::netreq::test_server::completion;
+    use crate::netreq::transport::MemoryTransport;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test_normalize() {
+        let a = json!({"model": "gpt-3.5-turbo", "messages": [], "temperature": 0.5, "top_p": null, "tools_hint": 1});
+        let b = json!({"temperature": 0.5, "messages": [], "model": "gpt-3.5-turbo"});
+        assert_eq!(normalize(&a), normalize(&b));
+        assert_ne!(normalize(&a), normalize(&json!({"model": "gpt-3.5-turbo", "messages": [], "temperature": 0.7})));
+    }
+
+    #[test]
+    fn test_record_then_replay() {
+        let path = std::env::temp_dir().join(format!("xtgptr-cassette-{}.jsonl", std::process::id()));
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let mut chat = Body::<String>::default();
+        chat.add_message(Message::new(Roles::User, String::from("Hello")));
+
+        let inner = MemoryTransport::new();
+        inner.push_reply_with_headers(200, &[("x-request-id", "req_1")], completion("Hi."));
+        let recorder = Cassette::record(&path, inner).unwrap();
+        assert!(recorder.is_recording());
+        let recorded = aw!(chat.perform_via(&token, &Config::default(), &recorder)).unwrap();
+        drop(recorder);
+        let written = std::fs::read_to_string(&path).unwrap();
+        assert!(!written.contains("sk-"));
+
+        let player = Cassette::replay(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // another base url, same endpoint and body
+        let config = Config::new("http://localhost:1/v1").unwrap();
+        let replayed = aw!(chat.perform_via(&token, &config, &player)).unwrap();
+        assert_eq!(format!("{:?}", recorded), format!("{:?}", replayed));
+        let again = aw!(player.send(HttpRequest::new(reqwest::Method::POST, config.url(crate::netreq::config::Endpoint::ChatCompletions), HeaderMap::new(), serde_json::to_vec(&chat).unwrap()))).unwrap();
+        assert_eq!(again.get_headers()["x-request-id"], "req_1");
+
+        chat.add_message(Message::new(Roles::User, String::from("Bye")));
+        assert!(aw!(chat.perform_via(&token, &config, &player)).is_err());
+    }
+}
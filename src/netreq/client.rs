@@ -0,0 +1,739 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
+use std::time::Instant;
+
+use futures::StreamExt;
+use futures::stream::BoxStream;
+use reqwest::Method;
+use reqwest::Proxy;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderValue;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::datas::AUTH_CONTENT_TYPE;
+use crate::datas::format::ResponseFormat;
+use crate::datas::request::Body;
+use crate::datas::request::Message;
+use crate::datas::request::Roles;
+use crate::datas::response::FinishReason;
+use crate::datas::response::Chunk;
+use crate::datas::response::Resp;
+use crate::datas::usage::TotalUsage;
+use crate::error::Error;
+use crate::netreq::config::Config;
+use crate::netreq::config::Endpoint;
+use crate::netreq::continuation::CONTINUE_PROMPT;
+use crate::netreq::continuation::Continued;
+use crate::netreq::event::Event;
+use crate::netreq::meta::Envelope;
+use crate::netreq::meta::ResponseMeta;
+use crate::netreq::perform::GenHeaders;
+use crate::netreq::perform::Outcome;
+use crate::netreq::perform::Signed;
+use crate::netreq::ratelimit::Permit;
+use crate::netreq::ratelimit::RateLimiter;
+use crate::netreq::retry::RetryPolicy;
+use crate::netreq::sse;
+use crate::netreq::transport::HttpRequest;
+use crate::netreq::transport::HttpResponse;
+use crate::netreq::transport::ReqwestTransport;
+use crate::netreq::transport::Transport;
+use crate::schema::JsonSchema;
+
+const USER_AGENT: &str = concat!("xtgptr/", env!("CARGO_PKG_VERSION"));
+
+/// Connection pool shared by every `perform` call made without a [`Client`]
+pub(crate) fn shared_transport() -> &'static ReqwestTransport {
+    static TRANSPORT: OnceLock<ReqwestTransport> = OnceLock::new();
+    TRANSPORT.get_or_init(|| ReqwestTransport::new(reqwest::Client::builder().user_agent(USER_AGENT).build().unwrap_or_default()))
+}
+
+async fn send(transport: &dyn Transport, request: HttpRequest) -> Result<HttpResponse, Error> {
+    let response = transport.send(request).await?;
+    match response.get_status() {
+        reqwest::StatusCode::OK => Ok(response),
+        status => {
+            let headers = response.get_headers().clone();
+            let body = response.bytes().await?;
+            Err(Error::from_response(status, headers, &body))
+        }
+    }
+}
+
+/// Send `body`, moving on to the next credentials of `auth` on 401 / 429 while its
+/// [`failover_limit`](GenHeaders::failover_limit) allows \
+/// Returns the response with the credentials that got it through.
+pub(crate) async fn post<Auth: GenHeaders + ?Sized, T: Serialize + ?Sized>(transport: &dyn Transport, auth: &Auth, config: &Config, body: &T) -> Result<(HttpResponse, Signed), Error> {
+    let body = serde_json::to_vec(body)?;
+    let mut tried = 0;
+    loop {
+        tried += 1;
+        let signed = auth.sign();
+        let mut headers = signed.get_headers().clone();
+        if !headers.contains_key(CONTENT_TYPE) {
+            headers.insert(CONTENT_TYPE, HeaderValue::from_static(AUTH_CONTENT_TYPE));
+        }
+        let request = HttpRequest::new(Method::POST, config.url(Endpoint::ChatCompletions), headers, body.clone());
+        match send(transport, request).await {
+            Ok(response) => return Ok((response, signed)),
+            Err(error) => {
+                auth.report(&signed, Outcome::Failure(&error));
+                let rejected = error.is_unauthorized() || error.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS);
+                if !rejected || tried >= auth.failover_limit() {
+                    return Err(error);
+                }
+                log::warn!("credentials rejected ({}), failing over to the next ones", error);
+            }
+        }
+    }
+}
+
+pub(crate) async fn chat<Auth: GenHeaders + ?Sized>(transport: &dyn Transport, auth: &Auth, config: &Config, body: &Body<String>) -> Result<Envelope<Resp<String>>, Error> {
+    let start = Instant::now();
+    let (response, sent) = post(transport, auth, config, body).await?;
+    let (status, headers) = (response.get_status(), response.get_headers().clone());
+    let body = response.bytes().await?;
+    let resp = serde_json::from_slice::<Resp<String>>(&body)?;
+    auth.report(&sent, Outcome::Success { tokens: Some(resp.total_tokens()) });
+    Ok(Envelope::new(resp, ResponseMeta::new(status, headers, start.elapsed())))
+}
+
+/// Sends the body with `stream` forced to true, whatever `set_stream` said \
+/// `include_usage` asks for a last chunk carrying the usage of the request.
+pub(crate) async fn chat_stream<Auth: GenHeaders + ?Sized>(transport: &dyn Transport, auth: &Auth, config: &Config, body: &Body<String>, include_usage: bool) -> Result<Envelope<ChunkStream>, Error> {
+    let start = Instant::now();
+    let mut body = serde_json::to_value(body)?;
+    body["stream"] = serde_json::Value::Bool(true);
+    if include_usage {
+        body["stream_options"] = serde_json::json!({ "include_usage": true });
+    }
+    let (response, sent) = post(transport, auth, config, &body).await?;
+    auth.report(&sent, Outcome::Success { tokens: None });
+    let meta = ResponseMeta::new(response.get_status(), response.get_headers().clone(), start.elapsed());
+    Ok(Envelope::new(sse::decode(response.into_stream()).boxed(), meta))
+}
+
+pub type ChunkStream = BoxStream<'static, Result<Chunk<String>, Error>>;
+
+type EventHook = Arc<dyn Fn(&Event<'_>) + Send + Sync>;
+
+struct Inner<Auth> {
+    transport: Arc<dyn Transport>,
+    auth: Auth,
+    config: Config,
+    defaults: Option<Body<String>>,
+    retry: RetryPolicy,
+    limiter: Option<RateLimiter>,
+    stream_usage: bool,
+    on_event: Option<EventHook>
+}
+
+/// Long-lived API client \
+/// Owns the connection pool, the credentials, the endpoint config and default request parameters. \
+/// Cloning is cheap and clones share the same pool, so one client can serve concurrent requests.
+pub struct Client<Auth> {
+    inner: Arc<Inner<Auth>>
+}
+
+impl<Auth> Clone for Client<Auth> {
+    fn clone(&self) -> Self {
+        Client { inner: self.inner.clone() }
+    }
+}
+
+impl<Auth: GenHeaders + Send + Sync> Client<Auth> {
+    /// Client for the default OpenAI endpoint
+    pub fn new(auth: Auth) -> Result<Client<Auth>, Error> {
+        Client::builder(auth).build()
+    }
+
+    pub fn builder(auth: Auth) -> ClientBuilder<Auth> {
+        ClientBuilder::new(auth)
+    }
+
+    pub async fn chat(&self, body: &Body<String>) -> Result<Resp<String>, Error> {
+        self.chat_with_meta(body).await.map(Envelope::into_body)
+    }
+
+    /// [`chat`](Client::chat), keeping request id, rate limits and latency of the successful attempt
+    pub async fn chat_with_meta(&self, body: &Body<String>) -> Result<Envelope<Resp<String>>, Error> {
+        let body = self.prepare(body);
+        let estimate = body.estimate_tokens();
+        self.with_retry(|| {
+            let body = &body;
+            async move {
+                let permit = self.admit(estimate).await;
+                let result = chat(self.inner.transport.as_ref(), &self.inner.auth, &self.inner.config, body).await;
+                match &result {
+                    Ok(gets) => self.settle(permit, Some(gets.get_body().total_tokens()), Some(gets.get_meta().get_headers())),
+                    Err(error) => self.settle_failure(permit, error)
+                }
+                result
+            }
+        }).await
+    }
+
+    /// [`chat`](Client::chat) asking for JSON following the schema of `T`, the answer parsed into `T` \
+    /// A `response_format` already set is replaced. Refusals and answers not following the schema are
+    /// [`Error::Refusal`] and [`Error::SchemaMismatch`], they are not retried.
+    pub async fn chat_typed<T: JsonSchema + DeserializeOwned>(&self, body: &Body<String>) -> Result<T, Error> {
+        let mut body = body.clone();
+        body.set_response_format(ResponseFormat::typed::<T>()?);
+        self.chat(&body).await?.parse_content()
+    }
+
+    /// [`chat`](Client::chat), asking for the rest while the first choice is cut by `max_tokens`
+    /// (finish reason `length`), at most `max_rounds` requests in all \
+    /// Each cut piece goes back as an assistant message followed by [`CONTINUE_PROMPT`], the pieces
+    /// are joined into one answer and the usage of every round is added up.
+    pub async fn chat_continued(&self, body: &Body<String>, max_rounds: usize) -> Result<Continued, Error> {
+        if max_rounds == 0 {
+            return Err(Error::validation("max_rounds", "max_rounds must be at least 1"));
+        }
+        let mut body = body.clone();
+        let mut content = String::new();
+        let mut usage = TotalUsage::default();
+        let mut rounds = 0;
+        loop {
+            rounds += 1;
+            let resp = self.chat(&body).await?;
+            usage += *resp.get_usage();
+            let choice = resp.get_choices().first().ok_or_else(|| Error::Decode(String::from("response has no choices")))?;
+            let piece = choice.get_message().get_content().to_text();
+            content.push_str(&piece);
+            if choice.get_finish_reason() != &FinishReason::Length || rounds >= max_rounds {
+                let finish_reason = choice.get_finish_reason().clone();
+                return Ok(Continued::new(content, usage, rounds, finish_reason, resp));
+            }
+            log::debug!("answer cut after {} round(s), asking for the rest", rounds);
+            body.add_message(Message::new(Roles::Assistant, piece));
+            body.add_message(Message::new(Roles::User, String::from(CONTINUE_PROMPT)));
+        }
+    }
+
+    /// Only opening the stream is retried, an error in the middle of it is handed to the caller \
+    /// With [`ClientBuilder::stream_usage`] the stream ends with a chunk with no choices carrying the usage.
+    pub async fn chat_stream(&self, body: &Body<String>) -> Result<ChunkStream, Error> {
+        self.chat_stream_with_meta(body).await.map(Envelope::into_body)
+    }
+
+    /// [`chat_stream`](Client::chat_stream), latency in the meta is the time until the response headers arrived
+    pub async fn chat_stream_with_meta(&self, body: &Body<String>) -> Result<Envelope<ChunkStream>, Error> {
+        let body = self.prepare(body);
+        let estimate = body.estimate_tokens();
+        let include_usage = self.inner.stream_usage;
+        self.with_retry(|| {
+            let body = &body;
+            async move {
+                let permit = self.admit(estimate).await;
+                match chat_stream(self.inner.transport.as_ref(), &self.inner.auth, &self.inner.config, body, include_usage).await {
+                    Ok(gets) => Ok(self.settle_stream(permit, gets)),
+                    Err(error) => {
+                        self.settle_failure(permit, &error);
+                        Err(error)
+                    }
+                }
+            }
+        }).await
+    }
+
+    pub fn get_auth(&self) -> &Auth {
+        &self.inner.auth
+    }
+
+    pub fn get_config(&self) -> &Config {
+        &self.inner.config
+    }
+
+    pub fn get_defaults(&self) -> Option<&Body<String>> {
+        self.inner.defaults.as_ref()
+    }
+
+    pub fn get_retry(&self) -> &RetryPolicy {
+        &self.inner.retry
+    }
+
+    pub fn get_rate_limiter(&self) -> Option<&RateLimiter> {
+        self.inner.limiter.as_ref()
+    }
+
+    async fn admit(&self, tokens: u32) -> Option<Permit> {
+        match &self.inner.limiter {
+            Some(limiter) => Some(limiter.acquire(tokens).await),
+            None => None
+        }
+    }
+
+    fn settle(&self, permit: Option<Permit>, used_tokens: Option<u32>, headers: Option<&HeaderMap>) {
+        if let (Some(limiter), Some(permit)) = (&self.inner.limiter, permit) {
+            limiter.settle(permit, used_tokens, headers);
+        }
+    }
+
+    /// A response from the server means no tokens were used and the reservation comes back,
+    /// a request lost on the way may have been processed so its estimate stands
+    fn settle_failure(&self, permit: Option<Permit>, error: &Error) {
+        self.settle(permit, error.status().map(|_| 0), error.headers());
+    }
+
+    /// Settle with the usage of the last chunk when it was asked for, the estimate stands if the stream ends without one \
+    /// Otherwise the estimate stands at once, corrected by the headers.
+    fn settle_stream(&self, permit: Option<Permit>, gets: Envelope<ChunkStream>) -> Envelope<ChunkStream> {
+        let (Some(limiter), Some(permit)) = (self.inner.limiter.clone(), permit) else {
+            return gets;
+        };
+        if !self.inner.stream_usage {
+            limiter.settle(permit, None, Some(gets.get_meta().get_headers()));
+            return gets;
+        }
+        limiter.observe(gets.get_meta().get_headers());
+        let (stream, meta) = gets.into_parts();
+        let mut permit = Some(permit);
+        let stream = stream.inspect(move |chunk| {
+            if let Some(usage) = chunk.as_ref().ok().and_then(Chunk::get_usage) {
+                if let Some(permit) = permit.take() {
+                    limiter.settle(permit, Some(usage.get_total_tokens()), None);
+                }
+            }
+        });
+        Envelope::new(stream.boxed(), meta)
+    }
+
+    fn emit(&self, event: Event<'_>) {
+        if let Some(hook) = &self.inner.on_event {
+            hook(&event);
+        }
+    }
+
+    async fn with_retry<T, F, Fut>(&self, mut send: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>
+    {
+        let policy = &self.inner.retry;
+        let mut attempt = 1;
+        loop {
+            log::debug!("sending attempt {}/{}", attempt, policy.get_max_attempts());
+            self.emit(Event::Attempt { attempt });
+            match send().await {
+                Ok(gets) => {
+                    self.emit(Event::Success { attempt });
+                    return Ok(gets);
+                },
+                Err(error) if attempt < policy.get_max_attempts() && policy.is_retryable(&error) => {
+                    let delay = policy.delay(attempt, &error);
+                    log::warn!("attempt {} failed ({}), retrying in {:?}", attempt, error, delay);
+                    self.emit(Event::Retry { attempt, delay, error: &error });
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                },
+                Err(error) => {
+                    log::warn!("attempt {} failed ({}), giving up", attempt, error);
+                    self.emit(Event::Failed { attempt, error: &error });
+                    return Err(error);
+                }
+            }
+        }
+    }
+
+    fn prepare(&self, body: &Body<String>) -> Body<String> {
+        let mut body = body.clone();
+        if let Some(defaults) = &self.inner.defaults {
+            body.fill_defaults(defaults);
+        }
+        body
+    }
+}
+
+pub struct ClientBuilder<Auth> {
+    auth: Auth,
+    config: Config,
+    defaults: Option<Body<String>>,
+    retry: RetryPolicy,
+    limiter: Option<RateLimiter>,
+    stream_usage: bool,
+    on_event: Option<EventHook>,
+    transport: Option<Arc<dyn Transport>>,
+    builder: reqwest::ClientBuilder
+}
+
+impl<Auth: GenHeaders + Send + Sync> ClientBuilder<Auth> {
+    pub fn new(auth: Auth) -> ClientBuilder<Auth> {
+        ClientBuilder {
+            auth,
+            config: Config::default(),
+            defaults: None,
+            retry: RetryPolicy::default(),
+            limiter: None,
+            stream_usage: false,
+            on_event: None,
+            transport: None,
+            builder: reqwest::Client::builder().user_agent(USER_AGENT)
+        }
+    }
+
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Parameters used for every optional field a request leaves unset, see [`Body::fill_defaults`]
+    pub fn defaults(mut self, defaults: Body<String>) -> Self {
+        self.defaults = Some(defaults);
+        self
+    }
+
+    /// Defaults to [`RetryPolicy::default`], use [`RetryPolicy::none`] to disable retries
+    pub fn retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Hold requests back so they stay within the quotas of `limiter` \
+    /// Pass clones of the same limiter to clients sharing a key
+    pub fn rate_limiter(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    /// Ask streams for `stream_options.include_usage`, a last chunk with no choices carrying the usage \
+    /// The rate limiter settles streams with it, without it they count as their estimate corrected by the headers.
+    pub fn stream_usage(mut self, stream_usage: bool) -> Self {
+        self.stream_usage = stream_usage;
+        self
+    }
+
+    /// Called for every attempt, retry and final failure
+    pub fn on_event(mut self, hook: impl Fn(&Event<'_>) + Send + Sync + 'static) -> Self {
+        self.on_event = Some(Arc::new(hook));
+        self
+    }
+
+    /// Whole-request timeout, including reading the body
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.builder = self.builder.timeout(timeout);
+        self
+    }
+
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.builder = self.builder.connect_timeout(timeout);
+        self
+    }
+
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self.builder = self.builder.proxy(proxy);
+        self
+    }
+
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.builder = self.builder.user_agent(user_agent);
+        self
+    }
+
+    /// Use an already configured `reqwest::Client`, other http settings of this builder are ignored
+    pub fn http_client(mut self, http: reqwest::Client) -> Self {
+        self.transport = Some(Arc::new(ReqwestTransport::new(http)));
+        self
+    }
+
+    /// Send through `transport` instead of reqwest, other http settings of this builder are ignored
+    pub fn transport(mut self, transport: impl Transport + 'static) -> Self {
+        self.transport = Some(Arc::new(transport));
+        self
+    }
+
+    /// Like [`transport`](ClientBuilder::transport), keeping a handle on it, e.g. to inspect a [`MemoryTransport`](super::transport::MemoryTransport)
+    pub fn shared_transport(mut self, transport: Arc<dyn Transport>) -> Self {
+        self.transport = Some(transport);
+        self
+    }
+
+    pub fn build(self) -> Result<Client<Auth>, Error> {
+        let transport = match self.transport {
+            Some(transport) => transport,
+            None => Arc::new(ReqwestTransport::new(self.builder.build()?))
+        };
+        Ok(Client { inner: Arc::new(Inner {
+            transport,
+            auth: self.auth,
+            config: self.config,
+            defaults: self.defaults,
+            retry: self.retry,
+            limiter: self.limiter,
+            stream_usage: self.stream_usage,
+            on_event: self.on_event
+        }) })
+    }
+}
+
+#[cfg(test)]
+mod client_tests {
+    use crate::datas::request::ChatLogin;
+    use crate::datas::request::Message;
+    use crate::datas::request::Roles;
+    use crate::datas::usage::Usage;
+    use crate::netreq::test_server::completion;
+    use crate::netreq::test_server::Reply;
+    use crate::netreq::test_server::TestServer;
+    use crate::netreq::transport::MemoryTransport;
+
+    use std::sync::Mutex;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test_client_defaults_and_config() {
+        let server = TestServer::start(vec![Reply::new(200, completion("Hi")), Reply::new(200, completion("Hi again"))]);
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let mut defaults = Body::<String>::default();
+        defaults.set_temperature(0.2).unwrap();
+        let client = Client::builder(token)
+            .config(Config::new(server.base_url()).unwrap())
+            .defaults(defaults)
+            .user_agent("batch-job/1.0")
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+
+        let mut chat = Body::<String>::default();
+        chat.add_message(Message::new(Roles::User, String::from("Hello")));
+        assert!(aw!(client.chat(&chat)).is_ok());
+        chat.set_temperature(1.0).unwrap();
+        assert!(aw!(client.clone().chat(&chat)).is_ok());
+
+        let recorded = server.recorded();
+        assert_eq!(recorded.len(), 2);
+        assert_eq!(recorded[0].path, "/v1/chat/completions");
+        assert_eq!(recorded[0].header("user-agent"), Some("batch-job/1.0"));
+        assert!(recorded[0].body.contains("\"temperature\":0.2"));
+        assert!(recorded[1].body.contains("\"temperature\":1.0"));
+    }
+
+    #[test]
+    fn test_client_api_error() {
+        let server = TestServer::start(vec![Reply::new(429, "{\"error\":{\"message\":\"Rate limit reached\",\"type\":\"requests\",\"code\":\"rate_limit_exceeded\"}}")]);
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let client = Client::builder(token).config(Config::new(server.base_url()).unwrap()).retry(RetryPolicy::none()).build().unwrap();
+        let err = aw!(client.chat(&Body::default())).unwrap_err();
+        assert!(err.is_rate_limited());
+    }
+
+    fn quick_retry(max_attempts: u32) -> RetryPolicy {
+        let mut policy = RetryPolicy::default();
+        policy.set_max_attempts(max_attempts).unwrap();
+        policy.set_base_delay(Duration::from_millis(1));
+        policy
+    }
+
+    #[test]
+    fn test_retry_until_success() {
+        let server = TestServer::start(vec![
+            Reply::new(503, "overloaded"),
+            Reply::new(429, "{\"error\":{\"message\":\"Rate limit reached\",\"code\":\"rate_limit_exceeded\"}}").header("retry-after-ms", "20"),
+            Reply::new(200, completion("Hi"))
+        ]);
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let mut policy = quick_retry(3);
+        policy.set_jitter(false);
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let record = events.clone();
+        let client = Client::builder(token)
+            .config(Config::new(server.base_url()).unwrap())
+            .retry(policy)
+            .on_event(move |event| record.lock().unwrap().push(match *event {
+                Event::Attempt { attempt } => ("attempt", attempt, None, None),
+                Event::Success { attempt } => ("success", attempt, None, None),
+                Event::Retry { attempt, delay, error } => ("retry", attempt, Some(delay), error.status()),
+                Event::Failed { attempt, error } => ("failed", attempt, None, error.status())
+            }))
+            .build()
+            .unwrap();
+
+        assert!(aw!(client.chat(&Body::default())).is_ok());
+        assert_eq!(server.recorded().len(), 3);
+        assert_eq!(*events.lock().unwrap(), [
+            ("attempt", 1, None, None),
+            ("retry", 1, Some(Duration::from_millis(1)), Some(reqwest::StatusCode::SERVICE_UNAVAILABLE)),
+            ("attempt", 2, None, None),
+            ("retry", 2, Some(Duration::from_millis(20)), Some(reqwest::StatusCode::TOO_MANY_REQUESTS)),
+            ("attempt", 3, None, None),
+            ("success", 3, None, None)
+        ]);
+    }
+
+    #[test]
+    fn test_retry_gives_up() {
+        let server = TestServer::start(vec![Reply::new(500, "boom"), Reply::new(502, "boom"), Reply::new(400, "never sent")]);
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let client = Client::builder(token).config(Config::new(server.base_url()).unwrap()).retry(quick_retry(2)).build().unwrap();
+        let err = aw!(client.chat(&Body::default())).unwrap_err();
+        assert_eq!(err.status(), Some(reqwest::StatusCode::BAD_GATEWAY));
+        assert_eq!(server.recorded().len(), 2);
+    }
+
+    #[test]
+    fn test_no_retry_on_bad_request() {
+        let server = TestServer::start(vec![Reply::new(400, "{\"error\":{\"message\":\"bad\",\"type\":\"invalid_request_error\"}}")]);
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let client = Client::builder(token).config(Config::new(server.base_url()).unwrap()).retry(quick_retry(5)).build().unwrap();
+        assert!(aw!(client.chat(&Body::default())).is_err());
+        assert_eq!(server.recorded().len(), 1);
+    }
+
+    fn limited_client(transport: &Arc<MemoryTransport>, limiter: RateLimiter) -> ClientBuilder<ChatLogin<&'static str>> {
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        Client::builder(token).shared_transport(transport.clone()).rate_limiter(limiter)
+    }
+
+    #[test]
+    fn test_rate_limiter_learns_from_headers() {
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_reply_with_headers(200, &[("x-ratelimit-remaining-requests", "0"), ("x-ratelimit-reset-requests", "300ms")], completion("Hi"))
+            .push_reply(200, completion("Hi again"));
+        let client = limited_client(&transport, RateLimiter::new(Some(10000), Some(1000000))).build().unwrap();
+        aw!(async {
+            tokio::time::pause();
+            let start = tokio::time::Instant::now();
+            assert!(client.chat(&Body::default()).await.is_ok());
+            assert_eq!(start.elapsed(), Duration::ZERO);
+            assert!(client.chat(&Body::default()).await.is_ok());
+            // the paused clock still rounds each sleep up to the next millisecond
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(300) && elapsed <= Duration::from_millis(301), "{:?}", elapsed);
+        });
+    }
+
+    /// Asks for more than the 6000 tokens per minute of [`refunding_limiter`] allow twice in a row
+    fn large_chat() -> Body<String> {
+        let mut chat = Body::<String>::default();
+        chat.add_message(Message::new(Roles::User, String::from("Hello")));
+        chat.set_max_tokens(5900).unwrap();
+        chat
+    }
+
+    fn refunding_limiter() -> RateLimiter {
+        RateLimiter::new(None, Some(6000))
+    }
+
+    #[test]
+    fn test_rate_limiter_refunds_failures() {
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_reply(429, "{\"error\":{\"message\":\"Rate limit reached\",\"code\":\"rate_limit_exceeded\"}}")
+            .push_reply(503, "overloaded")
+            .push_reply(200, completion("Hi"));
+        let mut policy = quick_retry(3);
+        policy.set_jitter(false);
+        let client = limited_client(&transport, refunding_limiter()).retry(policy).build().unwrap();
+        aw!(async {
+            tokio::time::pause();
+            let start = tokio::time::Instant::now();
+            assert!(client.chat(&large_chat()).await.is_ok());
+            // only the two backoffs of 1ms and 2ms, the refused requests held no tokens back
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(3) && elapsed <= Duration::from_millis(5), "{:?}", elapsed);
+        });
+        assert_eq!(transport.requests().len(), 3);
+    }
+
+    #[test]
+    fn test_rate_limiter_settles_streams() {
+        let chunk = |rest: &str| format!("data: {{\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":1,{}}}\n\n", rest);
+        let transport = Arc::new(MemoryTransport::new());
+        transport
+            .push_chunks(200, &[], vec![
+                chunk("\"choices\":[{\"index\":0,\"delta\":{\"content\":\"Hi\"},\"finish_reason\":\"stop\"}]"),
+                chunk("\"choices\":[],\"usage\":{\"prompt_tokens\":9,\"completion_tokens\":1,\"total_tokens\":10}"),
+                String::from("data: [DONE]\n\n")
+            ])
+            .push_reply(200, completion("Hi again"));
+        let client = limited_client(&transport, refunding_limiter()).stream_usage(true).build().unwrap();
+        aw!(async {
+            tokio::time::pause();
+            let start = tokio::time::Instant::now();
+            let chunks: Vec<_> = client.chat_stream(&large_chat()).await.unwrap().collect().await;
+            assert_eq!(chunks.last().unwrap().as_ref().unwrap().get_usage().map(Usage::get_total_tokens), Some(10));
+            assert!(client.chat(&large_chat()).await.is_ok());
+            assert_eq!(start.elapsed(), Duration::ZERO);
+        });
+        assert_eq!(transport.requests()[0].json()["stream_options"]["include_usage"], true);
+    }
+
+    #[test]
+    fn test_stream_usage_opt_in() {
+        let server = TestServer::start(vec![Reply::new(200, "data: [DONE]\n\n").header("x-ratelimit-remaining-tokens", "100")]);
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let client = Client::builder(token)
+            .config(Config::new(server.base_url()).unwrap())
+            .rate_limiter(refunding_limiter())
+            .build()
+            .unwrap();
+        let chunks: Vec<_> = aw!(aw!(client.chat_stream(&large_chat())).unwrap().collect::<Vec<_>>());
+        assert!(chunks.is_empty());
+        let first: serde_json::Value = serde_json::from_str(&server.recorded()[0].body).unwrap();
+        assert!(first.get("stream_options").is_none());
+    }
+
+    #[test]
+    fn test_chat_with_meta() {
+        let server = TestServer::start(vec![
+            Reply::new(200, completion("Hi")).header("x-request-id", "req_123").header("openai-processing-ms", "87").header("x-ratelimit-limit-tokens", "90000"),
+            Reply::new(404, "{\"error\":{\"message\":\"The model does not exist\",\"code\":\"model_not_found\"}}").header("x-request-id", "req_456")
+        ]);
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let client = Client::builder(token).config(Config::new(server.base_url()).unwrap()).build().unwrap();
+        let gets = aw!(client.chat_with_meta(&Body::default())).unwrap();
+        assert_eq!(gets.get_meta().get_request_id(), Some("req_123"));
+        assert_eq!(gets.get_meta().get_processing_ms(), Some(87));
+        assert_eq!(gets.get_meta().get_rate_limit().get_limit_tokens(), Some(90000));
+        assert!(gets.get_meta().get_latency() > Duration::ZERO);
+        let err = aw!(client.chat_with_meta(&Body::default())).unwrap_err();
+        assert_eq!(err.request_id(), Some("req_456"));
+    }
+
+    #[test]
+    fn test_chat_continued() {
+        let cut = |content: &str, finish: &str| format!("{{\"id\":\"chatcmpl-1\",\"object\":\"chat.completion\",\"created\":1,\"choices\":[{{\"index\":0,\"message\":{{\"role\":\"assistant\",\"content\":\"{}\"}},\"finish_reason\":\"{}\"}}],\"usage\":{{\"prompt_tokens\":10,\"completion_tokens\":4,\"total_tokens\":14}}}}", content, finish);
+        let server = TestServer::start(vec![
+            Reply::new(200, cut("Once upon ", "length")),
+            Reply::new(200, cut("a time, ", "length")),
+            Reply::new(200, cut("the end.", "stop")),
+            Reply::new(200, cut("Once upon ", "length")),
+            Reply::new(200, cut("a time, ", "length"))
+        ]);
+        let token = ChatLogin::new("Bearer sk-XXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXXX", None).unwrap();
+        let client = Client::builder(token).config(Config::new(server.base_url()).unwrap()).build().unwrap();
+        let mut chat = Body::<String>::default();
+        chat.add_message(Message::new(Roles::User, String::from("Tell a story")));
+
+        let gets = aw!(client.chat_continued(&chat, 5)).unwrap();
+        assert_eq!(gets.get_content(), "Once upon a time, the end.");
+        assert_eq!(gets.get_rounds(), 3);
+        assert!(gets.is_complete());
+        assert_eq!(gets.get_usage().get_total_tokens(), 42);
+        {
+            let recorded = server.recorded();
+            let last: serde_json::Value = serde_json::from_str(&recorded[2].body).unwrap();
+            assert_eq!(last["messages"].as_array().unwrap().len(), 5);
+            assert_eq!(last["messages"][3]["content"], "a time, ");
+            assert_eq!(last["messages"][4]["content"], CONTINUE_PROMPT);
+        }
+
+        let gets = aw!(client.chat_continued(&chat, 2)).unwrap();
+        assert_eq!(gets.get_content(), "Once upon a time, ");
+        assert_eq!(gets.get_finish_reason(), &FinishReason::Length);
+        assert!(!gets.is_complete());
+        assert!(aw!(client.chat_continued(&chat, 0)).is_err());
+    }
+}
@@ -0,0 +1,154 @@
+use reqwest::Url;
+
+use crate::datas::AZURE_API_VERSION;
+use crate::datas::CHAT_COMPLETIONS_PATH;
+use crate::datas::DEFAULT_BASE_URL;
+use crate::error::Error;
+
+/// API endpoints this crate knows how to call
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Endpoint {
+    ChatCompletions
+}
+
+/// Where requests are sent \
+/// Defaults to OpenAI, point `base_url` at any compatible server (gateway, vLLM, llama.cpp, mock server...)
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Config {
+    /// Everything before the endpoint path, e.g. "https://api.openai.com/v1"
+    base_url: String,
+    chat_completions_path: String,
+    /// Query parameters added to every url, e.g. `api-version` on Azure
+    query: Vec<(String, String)>
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            base_url: String::from(DEFAULT_BASE_URL),
+            chat_completions_path: String::from(CHAT_COMPLETIONS_PATH),
+            query: Vec::new()
+        }
+    }
+}
+
+impl Config {
+    pub fn new(base_url: impl Into<String>) -> Result<Config, Error> {
+        let mut config = Config::default();
+        config.set_base_url(base_url)?;
+        Ok(config)
+    }
+
+    /// Route to an Azure OpenAI deployment \
+    /// `endpoint` is the resource endpoint, e.g. "https://my-resource.openai.azure.com",
+    /// chat completions then go to "{endpoint}/openai/deployments/{deployment}/chat/completions?api-version={api_version}"
+    pub fn azure(endpoint: impl AsRef<str>, deployment: impl AsRef<str>, api_version: impl Into<String>) -> Result<Config, Error> {
+        let deployment = deployment.as_ref();
+        if deployment.is_empty() || !deployment.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.') {
+            return Err(Error::validation("deployment", format!("{} is not a valid deployment name", deployment)));
+        }
+        let api_version = api_version.into();
+        if api_version.trim().is_empty() {
+            return Err(Error::validation("api_version", "api_version is empty"));
+        }
+        let mut config = Config::new(format!("{}/openai", endpoint.as_ref().trim_end_matches('/')))?;
+        config.set_path(Endpoint::ChatCompletions, format!("/deployments/{}{}", deployment, CHAT_COMPLETIONS_PATH));
+        config.set_query(AZURE_API_VERSION, api_version);
+        Ok(config)
+    }
+
+    pub fn set_base_url(&mut self, base_url: impl Into<String>) -> Result<(), Error> {
+        let base_url = base_url.into();
+        match Url::parse(&base_url) {
+            Ok(url) if !url.cannot_be_a_base() => {
+                self.base_url = base_url.trim_end_matches('/').to_string();
+                Ok(())
+            },
+            _ => Err(Error::validation("base_url", format!("{} is not a valid base url", base_url)))
+        }
+    }
+
+    pub fn set_path(&mut self, endpoint: Endpoint, path: impl Into<String>) {
+        let path = path.into();
+        let path = match path.starts_with('/') {
+            true => path,
+            false => format!("/{}", path)
+        };
+        match endpoint {
+            Endpoint::ChatCompletions => self.chat_completions_path = path
+        }
+    }
+
+    /// Add a query parameter to every url, replacing any with the same name
+    pub fn set_query(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.query.retain(|(n, _)| *n != name);
+        self.query.push((name, value.into()));
+    }
+
+    pub fn remove_query(&mut self, name: &str) {
+        self.query.retain(|(n, _)| n != name);
+    }
+
+    pub fn get_base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn get_path(&self, endpoint: Endpoint) -> &str {
+        match endpoint {
+            Endpoint::ChatCompletions => &self.chat_completions_path
+        }
+    }
+
+    pub fn get_query(&self) -> &Vec<(String, String)> {
+        &self.query
+    }
+
+    /// Full url of an endpoint
+    pub fn url(&self, endpoint: Endpoint) -> String {
+        let url = format!("{}{}", self.base_url, self.get_path(endpoint));
+        match (self.query.is_empty(), Url::parse(&url)) {
+            (false, Ok(mut parsed)) => {
+                parsed.query_pairs_mut().extend_pairs(&self.query);
+                parsed.into()
+            },
+            _ => url
+        }
+    }
+}
+
+#[cfg(test)]
+mod config_tests {
+    use super::*;
+
+    #[test]
+    fn test_default_url() {
+        let config = Config::default();
+        assert_eq!(config.url(Endpoint::ChatCompletions), "https://api.openai.com/v1/chat/completions");
+    }
+
+    #[test]
+    fn test_custom_url() {
+        let mut config = Config::new("http://127.0.0.1:8000/v1/").unwrap();
+        assert_eq!(config.url(Endpoint::ChatCompletions), "http://127.0.0.1:8000/v1/chat/completions");
+        config.set_path(Endpoint::ChatCompletions, "openai/chat");
+        assert_eq!(config.url(Endpoint::ChatCompletions), "http://127.0.0.1:8000/v1/openai/chat");
+        assert!(config.set_base_url("not a url").is_err());
+        assert!(config.set_base_url("mailto:someone@example.com").is_err());
+        assert_eq!(config.get_base_url(), "http://127.0.0.1:8000/v1");
+    }
+
+    #[test]
+    fn test_azure_url() {
+        let mut config = Config::azure("https://my-resource.openai.azure.com/", "gpt35", "2024-02-01").unwrap();
+        assert_eq!(config.url(Endpoint::ChatCompletions), "https://my-resource.openai.azure.com/openai/deployments/gpt35/chat/completions?api-version=2024-02-01");
+        config.set_query("api-version", "2024-06-01");
+        assert_eq!(config.get_query().len(), 1);
+        assert!(config.url(Endpoint::ChatCompletions).ends_with("?api-version=2024-06-01"));
+        config.remove_query("api-version");
+        assert!(config.url(Endpoint::ChatCompletions).ends_with("/chat/completions"));
+        assert!(Config::azure("https://my-resource.openai.azure.com", "a/b", "2024-02-01").is_err());
+        assert!(Config::azure("https://my-resource.openai.azure.com", "gpt35", "").is_err());
+        assert!(Config::azure("my-resource", "gpt35", "2024-02-01").is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use crate::datas::response::FinishReason;
+use crate::datas::response::Resp;
+use crate::datas::usage::TotalUsage;
+
+/// User message asking for the rest of a cut answer
+pub const CONTINUE_PROMPT: &str = "Continue exactly where you stopped, without repeating anything.";
+
+/// Answer stitched from the rounds of [`Client::chat_continued`](super::client::Client::chat_continued)
+#[derive(Debug)]
+pub struct Continued {
+    content: String,
+    usage: TotalUsage,
+    rounds: usize,
+    /// Of the first choice of the last round
+    finish_reason: FinishReason,
+    last: Resp<String>
+}
+
+impl Continued {
+    pub(crate) fn new(content: String, usage: TotalUsage, rounds: usize, finish_reason: FinishReason, last: Resp<String>) -> Continued {
+        Continued { content, usage, rounds, finish_reason, last }
+    }
+
+    /// Content of every round, in order
+    pub fn get_content(&self) -> &str {
+        &self.content
+    }
+
+    /// Usage of every round added up
+    pub fn get_usage(&self) -> &TotalUsage {
+        &self.usage
+    }
+
+    /// Requests sent
+    pub fn get_rounds(&self) -> usize {
+        self.rounds
+    }
+
+    /// Finish reason of the last round, still `Length` when the round cap cut the answer
+    pub fn get_finish_reason(&self) -> &FinishReason {
+        &self.finish_reason
+    }
+
+    /// Whether the answer ended by itself rather than by the round cap
+    pub fn is_complete(&self) -> bool {
+        self.get_finish_reason() != &FinishReason::Length
+    }
+
+    /// Response of the last round
+    pub fn get_last(&self) -> &Resp<String> {
+        &self.last
+    }
+
+    pub fn into_content(self) -> String {
+        self.content
+    }
+}
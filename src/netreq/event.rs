@@ -0,0 +1,17 @@
+use std::time::Duration;
+
+use crate::error::Error;
+
+/// What a [`Client`](super::client::Client) is doing, reported to the hook set with `ClientBuilder::on_event` \
+/// `attempt` counts from 1
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// About to send the request
+    Attempt { attempt: u32 },
+    /// Attempt succeeded
+    Success { attempt: u32 },
+    /// Attempt failed, the request is sent again after `delay`
+    Retry { attempt: u32, delay: Duration, error: &'a Error },
+    /// Attempt failed and it was the last one
+    Failed { attempt: u32, error: &'a Error }
+}
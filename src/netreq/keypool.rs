@@ -0,0 +1,359 @@
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use reqwest::header::HeaderMap;
+
+use crate::datas::request::ChatLogin;
+use crate::error::Error;
+use crate::netreq::perform::GenHeaders;
+use crate::netreq::perform::Outcome;
+use crate::netreq::perform::Signed;
+
+/// How [`KeyPool`] picks the key of the next request, among the keys not cooling down
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Strategy {
+    /// One after the other
+    #[default]
+    RoundRobin,
+    /// The key rate limited longest ago (or never), fewest requests first on ties
+    LeastRecentlyLimited,
+    /// In proportion to the weight given in [`KeyPool::add_weighted`], spread evenly
+    Weighted
+}
+
+/// Counters of one key of a [`KeyPool`]
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct KeyStats {
+    /// Preview of the key, e.g. "sk-...abcd"
+    key: String,
+    organization: Option<String>,
+    weight: u32,
+    requests: u64,
+    successes: u64,
+    failures: u64,
+    rate_limited: u64,
+    unauthorized: u64,
+    /// Tokens reported by successful responses
+    tokens: u64,
+    /// Time left before the key is picked again, None if it is available
+    cooldown: Option<Duration>
+}
+
+impl KeyStats {
+    pub fn get_key(&self) -> &str {
+        &self.key
+    }
+
+    pub fn get_organization(&self) -> Option<&str> {
+        self.organization.as_deref()
+    }
+
+    pub fn get_weight(&self) -> u32 {
+        self.weight
+    }
+
+    pub fn get_requests(&self) -> u64 {
+        self.requests
+    }
+
+    pub fn get_successes(&self) -> u64 {
+        self.successes
+    }
+
+    pub fn get_failures(&self) -> u64 {
+        self.failures
+    }
+
+    pub fn get_rate_limited(&self) -> u64 {
+        self.rate_limited
+    }
+
+    pub fn get_unauthorized(&self) -> u64 {
+        self.unauthorized
+    }
+
+    pub fn get_tokens(&self) -> u64 {
+        self.tokens
+    }
+
+    pub fn get_cooldown(&self) -> Option<Duration> {
+        self.cooldown
+    }
+}
+
+#[derive(Debug, Default)]
+struct Health {
+    stats: KeyStats,
+    last_limited: Option<Instant>,
+    cooling_until: Option<Instant>,
+    /// Running credit of the weighted strategy
+    credit: i64
+}
+
+#[derive(Debug, Default)]
+struct State {
+    health: Vec<Health>,
+    next: usize
+}
+
+/// Several keys used as one [`GenHeaders`], e.g. to spread quota over keys of different organizations \
+/// A key answered with 401 or 429 cools down (for `retry-after` if the server says, else for the pool's cool-down)
+/// and is skipped meanwhile. Requests rejected that way are sent again with the next key right away,
+/// up to once per key.
+#[derive(Debug)]
+pub struct KeyPool {
+    logins: Vec<ChatLogin<String>>,
+    strategy: Strategy,
+    cooldown: Duration,
+    state: Mutex<State>
+}
+
+impl KeyPool {
+    pub fn new(strategy: Strategy) -> KeyPool {
+        KeyPool { logins: Vec::new(), strategy, cooldown: Duration::from_secs(60), state: Mutex::new(State::default()) }
+    }
+
+    pub fn add(&mut self, login: ChatLogin<String>) {
+        self.add_weighted(login, 1).unwrap();
+    }
+
+    /// Weight only matters to [`Strategy::Weighted`]
+    pub fn add_weighted(&mut self, login: ChatLogin<String>, weight: u32) -> Result<(), Error> {
+        if weight == 0 {
+            return Err(Error::validation("weight", "weight must be greater than 0"));
+        }
+        let stats = KeyStats {
+            key: login.get_auth().preview(),
+            organization: login.get_organization().cloned(),
+            weight,
+            ..KeyStats::default()
+        };
+        self.state.lock().unwrap().health.push(Health { stats, ..Health::default() });
+        self.logins.push(login);
+        Ok(())
+    }
+
+    /// How long a rejected key is skipped when the response doesn't say
+    pub fn set_cooldown(&mut self, cooldown: Duration) {
+        self.cooldown = cooldown;
+    }
+
+    pub fn get_strategy(&self) -> Strategy {
+        self.strategy
+    }
+
+    pub fn len(&self) -> usize {
+        self.logins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.logins.is_empty()
+    }
+
+    /// Counters of every key, in the order they were added
+    pub fn stats(&self) -> Vec<KeyStats> {
+        let now = Instant::now();
+        self.state.lock().unwrap().health.iter().map(|h| KeyStats {
+            cooldown: h.cooling_until.filter(|until| *until > now).map(|until| until - now),
+            ..h.stats.clone()
+        }).collect()
+    }
+
+    fn pick(&self, state: &mut State, now: Instant) -> usize {
+        let available: Vec<usize> = (0..state.health.len())
+            .filter(|i| state.health[*i].cooling_until.map(|until| until <= now).unwrap_or(true))
+            .collect();
+        if available.is_empty() {
+            // every key is cooling down, use the one back first rather than fail
+            return (0..state.health.len()).min_by_key(|i| state.health[*i].cooling_until).unwrap_or(0);
+        }
+        match self.strategy {
+            Strategy::RoundRobin => {
+                let picked = available.iter().copied().find(|i| *i >= state.next).unwrap_or(available[0]);
+                state.next = picked + 1;
+                picked
+            },
+            Strategy::LeastRecentlyLimited => {
+                available.into_iter().min_by_key(|i| (state.health[*i].last_limited, state.health[*i].stats.requests)).unwrap()
+            },
+            Strategy::Weighted => {
+                let total: i64 = available.iter().map(|i| state.health[*i].stats.weight as i64).sum();
+                for i in &available {
+                    state.health[*i].credit += state.health[*i].stats.weight as i64;
+                }
+                let picked = available.into_iter().max_by_key(|i| (state.health[*i].credit, -(*i as i64))).unwrap();
+                state.health[picked].credit -= total;
+                picked
+            }
+        }
+    }
+}
+
+impl GenHeaders for KeyPool {
+    fn gen_headers(&self) -> HeaderMap {
+        self.sign().into_headers()
+    }
+
+    /// Picks the key, the index of which comes back to [`report`](KeyPool::report)
+    fn sign(&self) -> Signed {
+        if self.logins.is_empty() {
+            return Signed::new(HeaderMap::new(), 0);
+        }
+        let mut state = self.state.lock().unwrap();
+        let picked = self.pick(&mut state, Instant::now());
+        state.health[picked].stats.requests += 1;
+        Signed::new(self.logins[picked].gen_headers(), picked)
+    }
+
+    fn report(&self, sent: &Signed, outcome: Outcome<'_>) {
+        let now = Instant::now();
+        let mut state = self.state.lock().unwrap();
+        let Some(health) = state.health.get_mut(sent.get_credentials()) else { return };
+        match outcome {
+            Outcome::Success { tokens } => {
+                health.stats.successes += 1;
+                health.stats.tokens += tokens.unwrap_or(0) as u64;
+            },
+            Outcome::Failure(error) => {
+                health.stats.failures += 1;
+                let limited = error.status() == Some(reqwest::StatusCode::TOO_MANY_REQUESTS);
+                if limited {
+                    health.stats.rate_limited += 1;
+                    health.last_limited = Some(now);
+                }
+                if error.is_unauthorized() {
+                    health.stats.unauthorized += 1;
+                }
+                if limited || error.is_unauthorized() {
+                    let cooldown = error.retry_after().filter(|_| limited).unwrap_or(self.cooldown);
+                    log::warn!("key {} cooling down for {:?}: {}", health.stats.key, cooldown, error);
+                    health.cooling_until = Some(now + cooldown);
+                }
+            }
+        }
+    }
+
+    fn failover_limit(&self) -> usize {
+        self.logins.len().max(1)
+    }
+}
+
+#[cfg(test)]
+mod keypool_tests {
+    use reqwest::header::AUTHORIZATION;
+
+    use crate::datas::request::Body;
+    use crate::netreq::config::Config;
+    use crate::netreq::perform::AsyncPerform;
+    use crate::netreq::test_server::completion;
+    use crate::netreq::transport::MemoryTransport;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn key(c: char) -> ChatLogin<String> {
+        ChatLogin::new(format!("sk-{}", c.to_string().repeat(48)), None).unwrap()
+    }
+
+    fn pool(strategy: Strategy, weights: &[u32]) -> KeyPool {
+        let mut pool = KeyPool::new(strategy);
+        for (i, weight) in weights.iter().enumerate() {
+            pool.add_weighted(key((b'A' + i as u8) as char), *weight).unwrap();
+        }
+        pool
+    }
+
+    fn picks(pool: &KeyPool, n: usize) -> String {
+        (0..n).map(|_| {
+            let headers = pool.gen_headers();
+            let auth = headers[AUTHORIZATION].to_str().unwrap().to_string();
+            auth.chars().last().unwrap()
+        }).collect()
+    }
+
+    fn rate_limited() -> Error {
+        Error::from_response(reqwest::StatusCode::TOO_MANY_REQUESTS, HeaderMap::new(), b"")
+    }
+
+    #[test]
+    fn test_strategies() {
+        assert_eq!(picks(&pool(Strategy::RoundRobin, &[1, 1, 1]), 5), "ABCAB");
+        assert_eq!(picks(&pool(Strategy::Weighted, &[5, 1, 1]), 7), "AABACAA");
+
+        let pool = pool(Strategy::LeastRecentlyLimited, &[1, 1]);
+        assert_eq!(picks(&pool, 2), "AB");
+        let sent = pool.sign();
+        assert_eq!(sent.get_credentials(), 0);
+        pool.report(&sent, Outcome::Failure(&rate_limited()));
+        assert!(pool.stats()[0].get_cooldown().is_some());
+        assert_eq!(picks(&pool, 2), "BB");
+        assert!(KeyPool::new(Strategy::Weighted).add_weighted(key('A'), 0).is_err());
+    }
+
+    #[test]
+    fn test_cooldown_expires() {
+        let mut pool = pool(Strategy::LeastRecentlyLimited, &[1, 1]);
+        pool.set_cooldown(Duration::from_millis(30));
+        let unauthorized = Error::from_response(reqwest::StatusCode::UNAUTHORIZED, HeaderMap::new(), b"");
+        pool.report(&Signed::new(key('A').gen_headers(), 0), Outcome::Failure(&unauthorized));
+        pool.report(&Signed::new(key('B').gen_headers(), 1), Outcome::Failure(&rate_limited()));
+        // both cooling, A is back first
+        assert_eq!(picks(&pool, 1), "A");
+        std::thread::sleep(Duration::from_millis(40));
+        // B was rate limited, A never was
+        assert_eq!(picks(&pool, 1), "A");
+        assert_eq!(pool.stats()[0].get_unauthorized(), 1);
+        assert_eq!(pool.stats()[1].get_rate_limited(), 1);
+    }
+
+    #[test]
+    fn test_same_key_twice() {
+        let mut same = KeyPool::new(Strategy::RoundRobin);
+        same.add(key('A'));
+        same.add(key('A'));
+        let first = same.sign();
+        let second = same.sign();
+        assert_eq!(first.get_headers(), second.get_headers());
+        assert_eq!((first.get_credentials(), second.get_credentials()), (0, 1));
+        same.report(&second, Outcome::Failure(&rate_limited()));
+        let stats = same.stats();
+        assert_eq!((stats[0].get_rate_limited(), stats[1].get_rate_limited()), (0, 1));
+        assert!(stats[0].get_cooldown().is_none() && stats[1].get_cooldown().is_some());
+    }
+
+    #[test]
+    fn test_failover() {
+        let transport = MemoryTransport::new();
+        transport
+            .push_reply_with_headers(429, &[("retry-after", "20")], "{\"error\":{\"message\":\"Rate limit reached\",\"type\":\"requests\",\"code\":\"rate_limit_exceeded\"}}")
+            .push_reply(401, "{\"error\":{\"message\":\"Incorrect API key provided\",\"type\":\"invalid_request_error\",\"code\":\"invalid_api_key\"}}")
+            .push_reply(200, completion("Hi"));
+        let pool = pool(Strategy::RoundRobin, &[1, 1, 1]);
+        aw!(Body::<String>::default().perform_via(&pool, &Config::default(), &transport)).unwrap();
+        let sent: Vec<String> = transport.requests().iter().map(|r| r.get_headers()[AUTHORIZATION].to_str().unwrap().to_string()).collect();
+        assert_eq!(sent.len(), 3);
+        assert!(sent[0].ends_with('A') && sent[1].ends_with('B') && sent[2].ends_with('C'));
+
+        let stats = pool.stats();
+        assert!(stats[0].get_cooldown().unwrap() > Duration::from_secs(19));
+        assert!(stats[1].get_cooldown().unwrap() > Duration::from_secs(59));
+        assert_eq!((stats[2].get_requests(), stats[2].get_successes(), stats[2].get_tokens()), (1, 1, 21));
+        assert_eq!(stats[2].get_key(), "sk-...CCCC");
+
+        // every key rejected: the last error comes back
+        transport.push_reply(429, "").push_reply(429, "").push_reply(429, "");
+        let err = aw!(Body::<String>::default().perform_via(&pool, &Config::default(), &transport)).unwrap_err();
+        assert_eq!(err.status(), Some(reqwest::StatusCode::TOO_MANY_REQUESTS));
+        assert_eq!(transport.remaining(), 0);
+        // not an auth or quota error: no fail-over
+        transport.push_reply(500, "");
+        assert!(aw!(Body::<String>::default().perform_via(&pool, &Config::default(), &transport)).is_err());
+        assert_eq!(transport.requests().len(), 7);
+    }
+}
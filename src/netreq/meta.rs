@@ -0,0 +1,123 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+
+use crate::netreq::ratelimit::RateLimitInfo;
+
+pub const REQUEST_ID: &str = "x-request-id";
+pub const PROCESSING_MS: &str = "openai-processing-ms";
+pub const MODEL: &str = "openai-model";
+
+/// Everything about a response except its body
+#[derive(Clone, Debug)]
+pub struct ResponseMeta {
+    status: StatusCode,
+    /// Quote this one in support tickets
+    request_id: Option<String>,
+    /// Time the server says it spent on the request
+    processing_ms: Option<u64>,
+    /// Model that actually served the request
+    model: Option<String>,
+    rate_limit: RateLimitInfo,
+    /// Wall-clock time from sending the request to having the whole body (to the headers for streams)
+    latency: Duration,
+    headers: HeaderMap
+}
+
+impl ResponseMeta {
+    pub fn new(status: StatusCode, headers: HeaderMap, latency: Duration) -> ResponseMeta {
+        let get = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(|v| v.trim().to_string());
+        ResponseMeta {
+            status,
+            request_id: get(REQUEST_ID),
+            processing_ms: get(PROCESSING_MS).and_then(|v| v.parse().ok()),
+            model: get(MODEL),
+            rate_limit: RateLimitInfo::from_headers(&headers),
+            latency,
+            headers
+        }
+    }
+
+    pub fn get_status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn get_request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    pub fn get_processing_ms(&self) -> Option<u64> {
+        self.processing_ms
+    }
+
+    pub fn get_model(&self) -> Option<&str> {
+        self.model.as_deref()
+    }
+
+    pub fn get_rate_limit(&self) -> &RateLimitInfo {
+        &self.rate_limit
+    }
+
+    pub fn get_latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// All response headers, for anything not parsed above
+    pub fn get_headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+}
+
+/// Parsed body of a response together with its [`ResponseMeta`]
+#[derive(Debug)]
+pub struct Envelope<T> {
+    body: T,
+    meta: ResponseMeta
+}
+
+impl<T> Envelope<T> {
+    pub fn new(body: T, meta: ResponseMeta) -> Envelope<T> {
+        Envelope { body, meta }
+    }
+
+    pub fn get_body(&self) -> &T {
+        &self.body
+    }
+
+    pub fn get_meta(&self) -> &ResponseMeta {
+        &self.meta
+    }
+
+    pub fn into_body(self) -> T {
+        self.body
+    }
+
+    pub fn into_parts(self) -> (T, ResponseMeta) {
+        (self.body, self.meta)
+    }
+}
+
+#[cfg(test)]
+mod meta_tests {
+    use super::*;
+
+    #[test]
+    fn test_meta_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(REQUEST_ID, "req_8f2e".parse().unwrap());
+        headers.insert(PROCESSING_MS, "412".parse().unwrap());
+        headers.insert(MODEL, "gpt-3.5-turbo-0301".parse().unwrap());
+        headers.insert("x-ratelimit-remaining-requests", "59".parse().unwrap());
+        let meta = ResponseMeta::new(StatusCode::OK, headers, Duration::from_millis(450));
+        assert_eq!(meta.get_request_id(), Some("req_8f2e"));
+        assert_eq!(meta.get_processing_ms(), Some(412));
+        assert_eq!(meta.get_model(), Some("gpt-3.5-turbo-0301"));
+        assert_eq!(meta.get_rate_limit().get_remaining_requests(), Some(59));
+        assert_eq!(meta.get_latency(), Duration::from_millis(450));
+
+        let empty = ResponseMeta::new(StatusCode::OK, HeaderMap::new(), Duration::ZERO);
+        assert_eq!(empty.get_request_id(), None);
+        assert_eq!(empty.get_processing_ms(), None);
+    }
+}
@@ -1,12 +1,123 @@
 use async_trait::async_trait;
+use futures::stream::BoxStream;
 use reqwest::header::HeaderMap;
+use serde::de::DeserializeOwned;
+
+use crate::error::Error;
+use crate::netreq::client::shared_transport;
+use crate::netreq::config::Config;
+use crate::netreq::meta::Envelope;
+use crate::netreq::transport::Transport;
+use crate::schema::JsonSchema;
+
+/// How a request sent with some credentials ended, see [`GenHeaders::report`]
+#[derive(Debug)]
+pub enum Outcome<'a> {
+    /// `tokens` used, when the response says
+    Success { tokens: Option<u32> },
+    Failure(&'a Error)
+}
+
+/// Headers of one request and which of the credentials of a [`GenHeaders`] made them, handed back to
+/// [`GenHeaders::report`]
+#[derive(Clone, Debug)]
+pub struct Signed {
+    headers: HeaderMap,
+    /// Index of the credentials among those the `GenHeaders` holds, 0 when it holds one
+    credentials: usize
+}
+
+impl Signed {
+    pub fn new(headers: HeaderMap, credentials: usize) -> Signed {
+        Signed { headers, credentials }
+    }
+
+    pub fn get_headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn get_credentials(&self) -> usize {
+        self.credentials
+    }
+
+    pub fn into_headers(self) -> HeaderMap {
+        self.headers
+    }
+}
 
 pub trait GenHeaders {
     fn gen_headers(&self) -> HeaderMap;
+    /// Headers of one request, telling [`report`](GenHeaders::report) which credentials were used
+    fn sign(&self) -> Signed {
+        Signed::new(self.gen_headers(), 0)
+    }
+    /// Told how the request sent with `sent` (as returned by [`sign`](GenHeaders::sign)) ended
+    fn report(&self, _sent: &Signed, _outcome: Outcome<'_>) {}
+    /// How many different credentials one request may try when the previous ones are rejected (401)
+    /// or out of quota (429), 1 disables fail-over
+    fn failover_limit(&self) -> usize {
+        1
+    }
 }
 
 #[async_trait]
-pub trait AsyncPerform<Auth: GenHeaders> {
+pub trait AsyncPerform<Auth: GenHeaders + Sync>: Sync {
     type Respr;
-    async fn perform(&self, auth: &Auth) -> Result<Self::Respr, String>; 
-}
\ No newline at end of file
+    /// Send to the default OpenAI endpoint
+    async fn perform(&self, auth: &Auth) -> Result<Self::Respr, Error> {
+        self.perform_with(auth, &Config::default()).await
+    }
+    /// Send to `config` through the shared reqwest connection pool
+    async fn perform_with(&self, auth: &Auth, config: &Config) -> Result<Self::Respr, Error> {
+        self.perform_via(auth, config, shared_transport()).await
+    }
+    async fn perform_via(&self, auth: &Auth, config: &Config, transport: &dyn Transport) -> Result<Self::Respr, Error> {
+        self.perform_meta_via(auth, config, transport).await.map(Envelope::into_body)
+    }
+    /// [`perform`](AsyncPerform::perform) keeping request id, rate limits and latency, like
+    /// [`Client::chat_with_meta`](crate::netreq::client::Client::chat_with_meta)
+    async fn perform_meta(&self, auth: &Auth) -> Result<Envelope<Self::Respr>, Error> {
+        self.perform_meta_with(auth, &Config::default()).await
+    }
+    async fn perform_meta_with(&self, auth: &Auth, config: &Config) -> Result<Envelope<Self::Respr>, Error> {
+        self.perform_meta_via(auth, config, shared_transport()).await
+    }
+    async fn perform_meta_via(&self, auth: &Auth, config: &Config, transport: &dyn Transport) -> Result<Envelope<Self::Respr>, Error>;
+}
+
+/// Same request as [`AsyncPerform`], but the answer arrives as server-sent chunks
+#[async_trait]
+pub trait AsyncStreamPerform<Auth: GenHeaders + Sync>: Sync {
+    type Chunk;
+    async fn perform_stream(&self, auth: &Auth) -> Result<BoxStream<'static, Result<Self::Chunk, Error>>, Error> {
+        self.perform_stream_with(auth, &Config::default()).await
+    }
+    async fn perform_stream_with(&self, auth: &Auth, config: &Config) -> Result<BoxStream<'static, Result<Self::Chunk, Error>>, Error> {
+        self.perform_stream_via(auth, config, shared_transport()).await
+    }
+    async fn perform_stream_via(&self, auth: &Auth, config: &Config, transport: &dyn Transport) -> Result<BoxStream<'static, Result<Self::Chunk, Error>>, Error> {
+        self.perform_stream_meta_via(auth, config, transport).await.map(Envelope::into_body)
+    }
+    /// [`perform_stream`](AsyncStreamPerform::perform_stream) keeping the meta of the response headers, like
+    /// [`Client::chat_stream_with_meta`](crate::netreq::client::Client::chat_stream_with_meta)
+    async fn perform_stream_meta(&self, auth: &Auth) -> Result<Envelope<BoxStream<'static, Result<Self::Chunk, Error>>>, Error> {
+        self.perform_stream_meta_with(auth, &Config::default()).await
+    }
+    async fn perform_stream_meta_with(&self, auth: &Auth, config: &Config) -> Result<Envelope<BoxStream<'static, Result<Self::Chunk, Error>>>, Error> {
+        self.perform_stream_meta_via(auth, config, shared_transport()).await
+    }
+    async fn perform_stream_meta_via(&self, auth: &Auth, config: &Config, transport: &dyn Transport) -> Result<Envelope<BoxStream<'static, Result<Self::Chunk, Error>>>, Error>;
+}
+
+/// Same request as [`AsyncPerform`], asking for JSON following the schema of `T` and parsing the answer into `T` \
+/// A `response_format` already set is replaced.
+#[async_trait]
+pub trait AsyncTypedPerform<Auth: GenHeaders + Sync>: Sync {
+    async fn perform_typed<T: JsonSchema + DeserializeOwned + Send>(&self, auth: &Auth) -> Result<T, Error> {
+        self.perform_typed_with::<T>(auth, &Config::default()).await
+    }
+    async fn perform_typed_with<T: JsonSchema + DeserializeOwned + Send>(&self, auth: &Auth, config: &Config) -> Result<T, Error> {
+        self.perform_typed_via::<T>(auth, config, shared_transport()).await
+    }
+    async fn perform_typed_via<T: JsonSchema + DeserializeOwned + Send>(&self, auth: &Auth, config: &Config, transport: &dyn Transport) -> Result<T, Error>;
+}
@@ -0,0 +1,303 @@
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use reqwest::header::HeaderMap;
+use tokio::time::Instant;
+
+const LIMIT_REQUESTS: &str = "x-ratelimit-limit-requests";
+const LIMIT_TOKENS: &str = "x-ratelimit-limit-tokens";
+const REMAINING_REQUESTS: &str = "x-ratelimit-remaining-requests";
+const REMAINING_TOKENS: &str = "x-ratelimit-remaining-tokens";
+const RESET_REQUESTS: &str = "x-ratelimit-reset-requests";
+const RESET_TOKENS: &str = "x-ratelimit-reset-tokens";
+
+/// `x-ratelimit-*` headers of a response, every field is None when its header is missing
+#[derive(Clone, Default, PartialEq, Eq, Debug)]
+pub struct RateLimitInfo {
+    limit_requests: Option<u32>,
+    limit_tokens: Option<u32>,
+    remaining_requests: Option<u32>,
+    remaining_tokens: Option<u32>,
+    /// Time until the request quota is fully restored
+    reset_requests: Option<Duration>,
+    /// Time until the token quota is fully restored
+    reset_tokens: Option<Duration>
+}
+
+impl RateLimitInfo {
+    pub fn from_headers(headers: &HeaderMap) -> RateLimitInfo {
+        let get = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).map(str::trim);
+        let number = |name: &str| get(name).and_then(|v| v.parse::<u32>().ok());
+        let reset = |name: &str| get(name).and_then(parse_reset);
+        RateLimitInfo {
+            limit_requests: number(LIMIT_REQUESTS),
+            limit_tokens: number(LIMIT_TOKENS),
+            remaining_requests: number(REMAINING_REQUESTS),
+            remaining_tokens: number(REMAINING_TOKENS),
+            reset_requests: reset(RESET_REQUESTS),
+            reset_tokens: reset(RESET_TOKENS)
+        }
+    }
+
+    pub fn get_limit_requests(&self) -> Option<u32> {
+        self.limit_requests
+    }
+
+    pub fn get_limit_tokens(&self) -> Option<u32> {
+        self.limit_tokens
+    }
+
+    pub fn get_remaining_requests(&self) -> Option<u32> {
+        self.remaining_requests
+    }
+
+    pub fn get_remaining_tokens(&self) -> Option<u32> {
+        self.remaining_tokens
+    }
+
+    pub fn get_reset_requests(&self) -> Option<Duration> {
+        self.reset_requests
+    }
+
+    pub fn get_reset_tokens(&self) -> Option<Duration> {
+        self.reset_tokens
+    }
+}
+
+/// Parse reset times like "20ms", "1s", "6m0s" or "1h2m3.5s"
+fn parse_reset(value: &str) -> Option<Duration> {
+    let mut total = 0.0;
+    let mut rest = value;
+    while !rest.is_empty() {
+        let split = rest.find(|c: char| !(c.is_ascii_digit() || c == '.'))?;
+        let (number, tail) = rest.split_at(split);
+        let number: f64 = number.parse().ok()?;
+        let unit_len = tail.find(|c: char| c.is_ascii_digit()).unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        total += number * match unit {
+            "ms" => 0.001,
+            "s" => 1.0,
+            "m" => 60.0,
+            "h" => 3600.0,
+            _ => return None
+        };
+        rest = tail;
+    }
+    Duration::try_from_secs_f64(total).ok()
+}
+
+/// Token bucket refilled continuously over one minute
+#[derive(Debug)]
+struct Bucket {
+    capacity: f64,
+    available: f64,
+    last: Instant,
+    /// Set when the server said the quota is used up
+    blocked_until: Option<Instant>
+}
+
+impl Bucket {
+    fn new(per_minute: u32, now: Instant) -> Bucket {
+        Bucket { capacity: per_minute as f64, available: per_minute as f64, last: now, blocked_until: None }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.available = (self.available + elapsed * self.capacity / 60.0).min(self.capacity);
+        self.last = now;
+    }
+
+    /// How long until `amount` can be taken, zero if it can be taken now
+    fn wait(&mut self, amount: f64, now: Instant) -> Duration {
+        self.refill(now);
+        if let Some(until) = self.blocked_until {
+            if until > now {
+                return until - now;
+            }
+            self.blocked_until = None;
+        }
+        let amount = amount.min(self.capacity);
+        match self.available >= amount {
+            true => Duration::ZERO,
+            false => Duration::from_secs_f64((amount - self.available) * 60.0 / self.capacity)
+        }
+    }
+
+    fn take(&mut self, amount: f64) {
+        self.available -= amount.min(self.capacity);
+    }
+
+    fn observe(&mut self, limit: Option<u32>, remaining: Option<u32>, reset: Option<Duration>, now: Instant) {
+        self.refill(now);
+        if let Some(limit) = limit {
+            self.capacity = self.capacity.min(limit as f64);
+        }
+        if let Some(remaining) = remaining {
+            self.available = self.available.min(remaining as f64);
+            if remaining == 0 {
+                self.blocked_until = reset.map(|r| now + r);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct State {
+    requests: Option<Bucket>,
+    tokens: Option<Bucket>
+}
+
+/// Keeps requests within requests-per-minute and tokens-per-minute quotas \
+/// Callers wait in [`acquire`](RateLimiter::acquire) until their request fits. The budget is corrected with
+/// the usage actually reported and narrowed by the `x-ratelimit-*` headers the server sends back. \
+/// Clones share the same budget, so one limiter can be handed to several clients using the same key.
+#[derive(Clone, Debug)]
+pub struct RateLimiter {
+    state: Arc<Mutex<State>>
+}
+
+/// Admission of one request, hand it back to [`RateLimiter::settle`] once the usage is known
+#[derive(Debug)]
+pub struct Permit {
+    tokens: u32
+}
+
+impl Permit {
+    pub fn get_tokens(&self) -> u32 {
+        self.tokens
+    }
+}
+
+impl RateLimiter {
+    /// None means no limit on that quota
+    pub fn new(requests_per_minute: Option<u32>, tokens_per_minute: Option<u32>) -> RateLimiter {
+        let now = Instant::now();
+        let state = State {
+            requests: requests_per_minute.map(|rpm| Bucket::new(rpm.max(1), now)),
+            tokens: tokens_per_minute.map(|tpm| Bucket::new(tpm.max(1), now))
+        };
+        RateLimiter { state: Arc::new(Mutex::new(state)) }
+    }
+
+    /// Wait until one request of `tokens` estimated tokens fits in both quotas, then reserve it
+    pub async fn acquire(&self, tokens: u32) -> Permit {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let state = &mut *state;
+                let wait_requests = state.requests.as_mut().map(|b| b.wait(1.0, now)).unwrap_or_default();
+                let wait_tokens = state.tokens.as_mut().map(|b| b.wait(tokens as f64, now)).unwrap_or_default();
+                let wait = wait_requests.max(wait_tokens);
+                if wait.is_zero() {
+                    if let Some(b) = state.requests.as_mut() {
+                        b.take(1.0);
+                    }
+                    if let Some(b) = state.tokens.as_mut() {
+                        b.take(tokens as f64);
+                    }
+                    return Permit { tokens };
+                }
+                wait
+            };
+            log::debug!("rate limiter holding request of {} tokens for {:?}", tokens, wait);
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Replace the estimate of `permit` by the tokens actually used, if known, and learn from the response headers \
+    /// Pass `Some(0)` for a request the server refused, its whole reservation comes back.
+    pub fn settle(&self, permit: Permit, used_tokens: Option<u32>, headers: Option<&HeaderMap>) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if let (Some(bucket), Some(used)) = (state.tokens.as_mut(), used_tokens) {
+                bucket.refill(Instant::now());
+                let reserved = (permit.tokens as f64).min(bucket.capacity);
+                bucket.available = (bucket.available + reserved - used as f64).min(bucket.capacity);
+            }
+        }
+        if let Some(headers) = headers {
+            self.observe(headers);
+        }
+    }
+
+    /// Narrow the budget to the `x-ratelimit-*` headers of a response, e.g. of a stream whose usage comes later
+    pub fn observe(&self, headers: &HeaderMap) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let info = RateLimitInfo::from_headers(headers);
+        if let Some(bucket) = state.requests.as_mut() {
+            bucket.observe(info.limit_requests, info.remaining_requests, info.reset_requests, now);
+        }
+        if let Some(bucket) = state.tokens.as_mut() {
+            bucket.observe(info.limit_tokens, info.remaining_tokens, info.reset_tokens, now);
+        }
+    }
+}
+
+#[cfg(test)]
+mod ratelimit_tests {
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    #[test]
+    fn test_parse_reset() {
+        assert_eq!(parse_reset("20ms"), Some(Duration::from_millis(20)));
+        assert_eq!(parse_reset("1s"), Some(Duration::from_secs(1)));
+        assert_eq!(parse_reset("6m0s"), Some(Duration::from_secs(360)));
+        assert_eq!(parse_reset("1h2m3.5s"), Some(Duration::from_secs_f64(3723.5)));
+        assert_eq!(parse_reset("soon"), None);
+        assert_eq!(parse_reset("3d"), None);
+    }
+
+    #[test]
+    fn test_info_from_headers() {
+        let mut headers = HeaderMap::new();
+        headers.insert(LIMIT_REQUESTS, "3500".parse().unwrap());
+        headers.insert(REMAINING_TOKENS, "89000".parse().unwrap());
+        headers.insert(RESET_TOKENS, "730ms".parse().unwrap());
+        let info = RateLimitInfo::from_headers(&headers);
+        assert_eq!(info.get_limit_requests(), Some(3500));
+        assert_eq!(info.get_limit_tokens(), None);
+        assert_eq!(info.get_remaining_tokens(), Some(89000));
+        assert_eq!(info.get_reset_tokens(), Some(Duration::from_millis(730)));
+    }
+
+    #[test]
+    fn test_bucket() {
+        let now = Instant::now();
+        let mut bucket = Bucket::new(60, now);
+        assert_eq!(bucket.wait(60.0, now), Duration::ZERO);
+        bucket.take(60.0);
+        assert_eq!(bucket.wait(1.0, now), Duration::from_secs(1));
+        assert_eq!(bucket.wait(1.0, now + Duration::from_secs(1)), Duration::ZERO);
+        // more than the whole quota is admitted once the bucket is full
+        assert_eq!(bucket.wait(1000.0, now + Duration::from_secs(60)), Duration::ZERO);
+        bucket.observe(Some(30), Some(0), Some(Duration::from_secs(5)), now + Duration::from_secs(60));
+        assert_eq!(bucket.capacity, 30.0);
+        assert_eq!(bucket.wait(1.0, now + Duration::from_secs(61)), Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_acquire_waits_for_tokens() {
+        aw!(async {
+            tokio::time::pause();
+            let limiter = RateLimiter::new(Some(1000), Some(6000));
+            let start = Instant::now();
+            let permit = limiter.acquire(6000).await;
+            assert_eq!(start.elapsed(), Duration::ZERO);
+            // 100 tokens per second come back, and the answer used 20 less than estimated
+            limiter.settle(permit, Some(5980), None);
+            limiter.acquire(40).await;
+            // the paused clock still rounds each sleep up to the next millisecond
+            let elapsed = start.elapsed();
+            assert!(elapsed >= Duration::from_millis(200) && elapsed <= Duration::from_millis(201), "{:?}", elapsed);
+        });
+    }
+}
@@ -0,0 +1,198 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::error::Error;
+use crate::error::TransportErrorKind;
+
+type E = Result<(), Error>;
+
+/// When and how often a failed request is sent again
+#[derive(Clone, PartialEq, Debug)]
+pub struct RetryPolicy {
+    /// Attempts in total, the first one included
+    max_attempts: u32,
+    /// Delay before the first retry, doubled for every next one
+    base_delay: Duration,
+    max_delay: Duration,
+    /// Pick the delay randomly between half and all of the backoff, so concurrent callers don't retry in lockstep
+    jitter: bool,
+    /// Wait as long as `retry-after` / `retry-after-ms` says instead of the backoff, and give up when that is
+    /// longer than `max_delay` rather than retry before the server allows
+    respect_retry_after: bool,
+    statuses: Vec<StatusCode>,
+    transport_errors: Vec<TransportErrorKind>
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            jitter: true,
+            respect_retry_after: true,
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT
+            ],
+            transport_errors: vec![TransportErrorKind::Connect, TransportErrorKind::Timeout]
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry
+    pub fn none() -> RetryPolicy {
+        RetryPolicy { max_attempts: 1, ..RetryPolicy::default() }
+    }
+
+    pub fn set_max_attempts(&mut self, max_attempts: u32) -> E {
+        match max_attempts < 1 {
+            true => Err(Error::validation("max_attempts", "max_attempts must be greater than 0")),
+            false => {
+                self.max_attempts = max_attempts;
+                Ok(())
+            }
+        }
+    }
+
+    pub fn set_base_delay(&mut self, base_delay: Duration) {
+        self.base_delay = base_delay;
+    }
+
+    pub fn set_max_delay(&mut self, max_delay: Duration) {
+        self.max_delay = max_delay;
+    }
+
+    pub fn set_jitter(&mut self, jitter: bool) {
+        self.jitter = jitter;
+    }
+
+    pub fn set_respect_retry_after(&mut self, respect_retry_after: bool) {
+        self.respect_retry_after = respect_retry_after;
+    }
+
+    /// Replace the retryable status codes
+    pub fn set_statuses(&mut self, statuses: Vec<StatusCode>) {
+        self.statuses = statuses;
+    }
+
+    /// Replace the retryable kinds of transport errors
+    pub fn set_transport_errors(&mut self, transport_errors: Vec<TransportErrorKind>) {
+        self.transport_errors = transport_errors;
+    }
+
+    pub fn get_max_attempts(&self) -> u32 {
+        self.max_attempts
+    }
+
+    pub fn get_statuses(&self) -> &Vec<StatusCode> {
+        &self.statuses
+    }
+
+    pub fn get_transport_errors(&self) -> &Vec<TransportErrorKind> {
+        &self.transport_errors
+    }
+
+    /// Whether `error` is worth another attempt \
+    /// An exhausted quota is never retried, waiting won't bring it back, nor is a `retry-after` longer than `max_delay`
+    /// when it is respected
+    pub fn is_retryable(&self, error: &Error) -> bool {
+        match error {
+            Error::Transport(x) => self.transport_errors.contains(&x.get_kind()),
+            _ if error.is_quota_exceeded() => false,
+            _ if self.respect_retry_after && error.retry_after().map(|after| after > self.max_delay).unwrap_or(false) => false,
+            _ => error.status().map(|s| self.statuses.contains(&s)).unwrap_or(false)
+        }
+    }
+
+    /// Delay before attempt `attempt + 1`, `attempt` counting from 1 \
+    /// The whole `retry-after` when it is respected, [`is_retryable`](RetryPolicy::is_retryable) refuses the ones over `max_delay`
+    pub fn delay(&self, attempt: u32, error: &Error) -> Duration {
+        if self.respect_retry_after {
+            if let Some(after) = error.retry_after() {
+                return after;
+            }
+        }
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let backoff = self.base_delay.saturating_mul(factor).min(self.max_delay);
+        match self.jitter {
+            true => backoff.mul_f64(0.5 + random_unit() / 2.0),
+            false => backoff
+        }
+    }
+}
+
+/// Random number in [0, 1), good enough for jitter
+fn random_unit() -> f64 {
+    let bits = RandomState::new().hash_one(std::time::SystemTime::now());
+    (bits >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use reqwest::header::HeaderMap;
+
+    use crate::error::TransportError;
+
+    use super::*;
+
+    fn status(code: u16, body: &[u8]) -> Error {
+        Error::from_response(StatusCode::from_u16(code).unwrap(), HeaderMap::new(), body)
+    }
+
+    #[test]
+    fn test_retryable() {
+        let mut policy = RetryPolicy::default();
+        assert!(policy.is_retryable(&status(429, b"")));
+        assert!(policy.is_retryable(&status(503, b"")));
+        assert!(!policy.is_retryable(&status(400, b"")));
+        assert!(!policy.is_retryable(&status(429, b"{\"error\":{\"message\":\"quota\",\"code\":\"insufficient_quota\"}}")));
+        assert!(policy.is_retryable(&Error::Transport(TransportError::new(TransportErrorKind::Connect, "refused"))));
+        assert!(!policy.is_retryable(&Error::Transport(TransportError::new(TransportErrorKind::Other, "tls"))));
+        assert!(!policy.is_retryable(&Error::validation("temperature", "too hot")));
+        policy.set_statuses(vec![StatusCode::BAD_REQUEST]);
+        assert!(policy.is_retryable(&status(400, b"")));
+        assert!(!policy.is_retryable(&status(429, b"")));
+        assert!(policy.set_max_attempts(0).is_err());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let mut policy = RetryPolicy::default();
+        policy.set_jitter(false);
+        policy.set_base_delay(Duration::from_millis(100));
+        policy.set_max_delay(Duration::from_millis(350));
+        let err = status(503, b"");
+        assert_eq!(policy.delay(1, &err), Duration::from_millis(100));
+        assert_eq!(policy.delay(2, &err), Duration::from_millis(200));
+        assert_eq!(policy.delay(3, &err), Duration::from_millis(350));
+        assert_eq!(policy.delay(40, &err), Duration::from_millis(350));
+        policy.set_jitter(true);
+        for _ in 0..20 {
+            let delay = policy.delay(2, &err);
+            assert!(delay >= Duration::from_millis(100) && delay <= Duration::from_millis(200));
+        }
+    }
+
+    #[test]
+    fn test_retry_after() {
+        let mut headers = HeaderMap::new();
+        headers.insert("retry-after", "7".parse().unwrap());
+        let err = Error::from_response(StatusCode::TOO_MANY_REQUESTS, headers, b"");
+        let mut policy = RetryPolicy::default();
+        assert_eq!(policy.delay(1, &err), Duration::from_secs(7));
+        assert!(policy.is_retryable(&err));
+        policy.set_max_delay(Duration::from_secs(5));
+        assert!(!policy.is_retryable(&err));
+        policy.set_respect_retry_after(false);
+        assert!(policy.is_retryable(&err));
+        assert!(policy.delay(1, &err) <= Duration::from_millis(500));
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::VecDeque;
+
+use futures::Stream;
+use futures::StreamExt;
+use futures::stream;
+use serde::de::DeserializeOwned;
+use reqwest::StatusCode;
+
+use crate::error::ApiErrorBody;
+use crate::error::Error;
+use crate::error::TransportError;
+
+/// Data sent as the last event of a stream
+pub const DONE: &str = "[DONE]";
+
+/// One dispatched [server-sent event](https://html.spec.whatwg.org/multipage/server-sent-events.html#event-stream-interpretation)
+#[derive(PartialEq, Eq, Debug)]
+pub struct Event {
+    event: Option<String>,
+    data: String
+}
+
+impl Event {
+    pub fn get_event(&self) -> Option<&str> {
+        self.event.as_deref()
+    }
+
+    pub fn get_data(&self) -> &str {
+        &self.data
+    }
+}
+
+/// Incremental event-stream decoder \
+/// Bytes may be fed in arbitrary pieces, events come out once their terminating blank line arrives
+#[derive(Default)]
+pub struct Decoder {
+    buf: Vec<u8>,
+    event: Option<String>,
+    data: Option<String>
+}
+
+impl Decoder {
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<Event> {
+        self.buf.extend_from_slice(bytes);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|b| *b == b'\n') {
+            let mut line: Vec<u8> = self.buf.drain(..=pos).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            if let Some(event) = self.line(&String::from_utf8_lossy(&line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Dispatch whatever is left when the underlying stream ends
+    pub fn finish(&mut self) -> Option<Event> {
+        let rest = std::mem::take(&mut self.buf);
+        if !rest.is_empty() {
+            if let Some(event) = self.line(&String::from_utf8_lossy(&rest)) {
+                return Some(event);
+            }
+        }
+        self.line("")
+    }
+
+    fn line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            let event = self.event.take();
+            return self.data.take().map(|data| Event { event, data });
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, "")
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                },
+                None => self.data = Some(value.to_string())
+            },
+            _ => ()
+        };
+        None
+    }
+}
+
+struct State<B> {
+    bytes: B,
+    decoder: Decoder,
+    pending: VecDeque<Event>,
+    /// `bytes` ended or failed, it must not be polled again
+    done: bool
+}
+
+/// Turn a byte stream of `data:` events into typed items, ending at `data: [DONE]`
+/// An error object sent mid-stream (as `data: {"error": ...}` or `event: error`) ends the stream with [`Error::Api`]
+pub fn decode<T, B, C, E>(bytes: B) -> impl Stream<Item = Result<T, Error>>
+where
+    T: DeserializeOwned,
+    B: Stream<Item = Result<C, E>> + Unpin,
+    C: AsRef<[u8]>,
+    E: Into<TransportError>
+{
+    let state = State { bytes, decoder: Decoder::new(), pending: VecDeque::new(), done: false };
+    stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(event) = state.pending.pop_front() {
+                let data = event.get_data();
+                if data == DONE {
+                    return None;
+                }
+                if let Ok(gets) = serde_json::from_str::<ApiErrorBody>(data) {
+                    state.done = true;
+                    state.pending.clear();
+                    return Some((Err(Error::Api { status: StatusCode::OK, headers: Box::default(), error: gets.error }), state));
+                }
+                if event.get_event() == Some("error") {
+                    state.done = true;
+                    state.pending.clear();
+                    return Some((Err(Error::Decode(format!("stream error event: {}", data))), state));
+                }
+                let item = serde_json::from_str::<T>(data).map_err(Error::from);
+                return Some((item, state));
+            }
+            if state.done {
+                return None;
+            }
+            match state.bytes.next().await {
+                Some(Ok(chunk)) => {
+                    let events = state.decoder.feed(chunk.as_ref());
+                    state.pending.extend(events);
+                },
+                Some(Err(x)) => {
+                    state.done = true;
+                    return Some((Err(Error::Transport(x.into())), state));
+                },
+                None => {
+                    state.done = true;
+                    state.pending.extend(state.decoder.finish());
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod sse_tests {
+    use futures::StreamExt;
+    use futures::stream;
+
+    use crate::datas::response::Chunk;
+
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn chunk(content: &str) -> String {
+        format!("data: {{\"id\":\"chatcmpl-1\",\"object\":\"chat.completion.chunk\",\"created\":1,\"choices\":[{{\"index\":0,\"delta\":{{\"content\":\"{}\"}},\"finish_reason\":null}}]}}\n\n", content)
+    }
+
+    #[test]
+    fn test_decoder_split_lines() {
+        let mut decoder = Decoder::new();
+        assert!(decoder.feed(b": keep-alive\n\nda").is_empty());
+        assert!(decoder.feed(b"ta: a\r\ndata:b\r").is_empty());
+        let events = decoder.feed(b"\n\nevent: error\ndata: c\n\n");
+        assert_eq!(events, vec![
+            Event { event: None, data: String::from("a\nb") },
+            Event { event: Some(String::from("error")), data: String::from("c") }
+        ]);
+        assert_eq!(decoder.feed(b"data: d"), vec![]);
+        assert_eq!(decoder.finish(), Some(Event { event: None, data: String::from("d") }));
+    }
+
+    #[test]
+    fn test_decode_until_done() {
+        let raw = format!("{}{}data: [DONE]\n\n{}", chunk("Hel"), chunk("lo"), chunk("ignored"));
+        let (a, b) = raw.split_at(37);
+        let bytes = stream::iter(vec![Ok::<_, TransportError>(a.as_bytes().to_vec()), Ok(b.as_bytes().to_vec())]);
+        let chunks: Vec<Result<Chunk<String>, Error>> = aw!(decode(bytes).collect());
+        let contents: Vec<String> = chunks.into_iter()
+            .map(|c| c.unwrap().get_choices()[0].get_delta().get_content().unwrap().clone())
+            .collect();
+        assert_eq!(contents, vec!["Hel", "lo"]);
+    }
+
+    /// Panics when polled again after it ended, as streams that are not fused may
+    struct Unfused {
+        chunks: VecDeque<Vec<u8>>,
+        ended: bool
+    }
+
+    impl Stream for Unfused {
+        type Item = Result<Vec<u8>, TransportError>;
+
+        fn poll_next(mut self: std::pin::Pin<&mut Self>, _: &mut std::task::Context<'_>) -> std::task::Poll<Option<Self::Item>> {
+            assert!(!self.ended, "polled after the end");
+            let next = self.chunks.pop_front();
+            self.ended = next.is_none();
+            std::task::Poll::Ready(next.map(Ok))
+        }
+    }
+
+    #[test]
+    fn test_decode_trailing_event() {
+        // no blank line after the last event, it is dispatched when the bytes end
+        let raw = format!("{}{}", chunk("Hel"), chunk("lo").trim_end());
+        let bytes = Unfused { chunks: VecDeque::from([raw.into_bytes()]), ended: false };
+        let chunks: Vec<Result<Chunk<String>, Error>> = aw!(decode(bytes).collect());
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(chunks[1].as_ref().unwrap().get_choices()[0].get_delta().get_content().unwrap(), "lo");
+    }
+
+    #[test]
+    fn test_decode_error_event() {
+        let raw = format!("{}data: {{\"error\":{{\"message\":\"overloaded\",\"type\":\"server_error\"}}}}\n\n{}", chunk("a"), chunk("b"));
+        let bytes = stream::iter(vec![Ok::<_, TransportError>(raw.into_bytes())]);
+        let chunks: Vec<Result<Chunk<String>, Error>> = aw!(decode(bytes).collect());
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks[0].is_ok());
+        assert_eq!(chunks[1].as_ref().unwrap_err().api_error().unwrap().get_message(), "overloaded");
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures::StreamExt;
+use futures::TryStreamExt;
+use futures::stream;
+use futures::stream::BoxStream;
+use reqwest::Method;
+use reqwest::StatusCode;
+use reqwest::header::HeaderMap;
+use reqwest::header::HeaderName;
+use reqwest::header::HeaderValue;
+
+use crate::error::TransportError;
+use crate::error::TransportErrorKind;
+
+/// Response body as it arrives
+pub type ByteStream = BoxStream<'static, Result<Bytes, TransportError>>;
+
+/// Request as handed to a [`Transport`]
+#[derive(Clone, Debug)]
+pub struct HttpRequest {
+    method: Method,
+    url: String,
+    headers: HeaderMap,
+    body: Vec<u8>
+}
+
+impl HttpRequest {
+    pub fn new(method: Method, url: String, headers: HeaderMap, body: Vec<u8>) -> HttpRequest {
+        HttpRequest { method, url, headers, body }
+    }
+
+    pub fn get_method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn get_url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn get_headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn get_body(&self) -> &[u8] {
+        &self.body
+    }
+
+    /// Body parsed as JSON, `Null` if it is not JSON
+    pub fn json(&self) -> serde_json::Value {
+        serde_json::from_slice(&self.body).unwrap_or_default()
+    }
+}
+
+/// Response as returned by a [`Transport`], whatever its status
+pub struct HttpResponse {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: ByteStream
+}
+
+impl HttpResponse {
+    pub fn new(status: StatusCode, headers: HeaderMap, body: ByteStream) -> HttpResponse {
+        HttpResponse { status, headers, body }
+    }
+
+    /// Response whose whole body is already known
+    pub fn full(status: StatusCode, headers: HeaderMap, body: impl Into<Bytes>) -> HttpResponse {
+        let body = body.into();
+        HttpResponse::new(status, headers, stream::once(async move { Ok(body) }).boxed())
+    }
+
+    pub fn get_status(&self) -> StatusCode {
+        self.status
+    }
+
+    pub fn get_headers(&self) -> &HeaderMap {
+        &self.headers
+    }
+
+    pub fn into_stream(self) -> ByteStream {
+        self.body
+    }
+
+    /// Read the rest of the body
+    pub async fn bytes(self) -> Result<Vec<u8>, TransportError> {
+        self.body.try_fold(Vec::new(), |mut all, chunk| async move {
+            all.extend_from_slice(&chunk);
+            Ok(all)
+        }).await
+    }
+}
+
+/// The HTTP layer under every request of this crate \
+/// Implement it to route requests through another client, or use [`MemoryTransport`] to run without network.
+#[async_trait]
+pub trait Transport: Send + Sync {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError>;
+}
+
+/// Transport over a `reqwest::Client`, the default
+#[derive(Clone, Default, Debug)]
+pub struct ReqwestTransport {
+    http: reqwest::Client
+}
+
+impl ReqwestTransport {
+    pub fn new(http: reqwest::Client) -> ReqwestTransport {
+        ReqwestTransport { http }
+    }
+}
+
+#[async_trait]
+impl Transport for ReqwestTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        let response = self.http.request(request.method, request.url)
+            .headers(request.headers)
+            .body(request.body)
+            .send()
+            .await?;
+        let status = response.status();
+        let headers = response.headers().clone();
+        Ok(HttpResponse::new(status, headers, response.bytes_stream().map_err(TransportError::from).boxed()))
+    }
+}
+
+enum Scripted {
+    Reply { status: StatusCode, headers: HeaderMap, chunks: Vec<Bytes> },
+    Fail(TransportErrorKind, String)
+}
+
+/// In-memory transport answering with scripted replies, in order, and recording every request
+#[derive(Default)]
+pub struct MemoryTransport {
+    replies: Mutex<VecDeque<Scripted>>,
+    requests: Mutex<Vec<HttpRequest>>
+}
+
+impl MemoryTransport {
+    pub fn new() -> MemoryTransport {
+        MemoryTransport::default()
+    }
+
+    /// Queue a reply with a whole body
+    pub fn push_reply(&self, status: u16, body: impl Into<String>) -> &Self {
+        self.push_reply_with_headers(status, &[], body)
+    }
+
+    pub fn push_reply_with_headers(&self, status: u16, headers: &[(&str, &str)], body: impl Into<String>) -> &Self {
+        self.push_chunks(status, headers, vec![body.into()])
+    }
+
+    /// Queue a reply whose body arrives in several pieces, e.g. server-sent events
+    pub fn push_chunks(&self, status: u16, headers: &[(&str, &str)], chunks: Vec<String>) -> &Self {
+        let mut map = HeaderMap::new();
+        for (k, v) in headers {
+            map.insert(HeaderName::from_bytes(k.as_bytes()).unwrap(), HeaderValue::from_str(v).unwrap());
+        }
+        let status = StatusCode::from_u16(status).unwrap();
+        self.replies.lock().unwrap().push_back(Scripted::Reply { status, headers: map, chunks: chunks.into_iter().map(Bytes::from).collect() });
+        self
+    }
+
+    /// Queue a failure to get any response
+    pub fn push_error(&self, kind: TransportErrorKind, message: &str) -> &Self {
+        self.replies.lock().unwrap().push_back(Scripted::Fail(kind, message.to_string()));
+        self
+    }
+
+    /// Every request sent so far
+    pub fn requests(&self) -> Vec<HttpRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Scripted replies not consumed yet
+    pub fn remaining(&self) -> usize {
+        self.replies.lock().unwrap().len()
+    }
+}
+
+#[async_trait]
+impl Transport for MemoryTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse, TransportError> {
+        self.requests.lock().unwrap().push(request);
+        match self.replies.lock().unwrap().pop_front() {
+            Some(Scripted::Reply { status, headers, chunks }) => {
+                Ok(HttpResponse::new(status, headers, stream::iter(chunks.into_iter().map(Ok)).boxed()))
+            },
+            Some(Scripted::Fail(kind, message)) => Err(TransportError::new(kind, message)),
+            None => Err(TransportError::new(TransportErrorKind::Other, "no scripted reply left"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod transport_tests {
+    use super::*;
+
+    macro_rules! aw {
+        ($e:expr) => {
+            tokio_test::block_on($e)
+        };
+    }
+
+    fn request() -> HttpRequest {
+        HttpRequest::new(Method::POST, String::from("http://localhost/v1/chat/completions"), HeaderMap::new(), b"{\"model\":\"m\"}".to_vec())
+    }
+
+    #[test]
+    fn test_memory_transport() {
+        let transport = MemoryTransport::new();
+        transport
+            .push_reply_with_headers(200, &[("x-request-id", "req_1")], "{}")
+            .push_chunks(200, &[], vec![String::from("data: a\n"), String::from("\n")])
+            .push_error(TransportErrorKind::Timeout, "timed out");
+
+        let first = aw!(transport.send(request())).unwrap();
+        assert_eq!(first.get_headers()["x-request-id"], "req_1");
+        assert_eq!(aw!(first.bytes()).unwrap(), b"{}");
+        let second = aw!(transport.send(request())).unwrap();
+        assert_eq!(aw!(second.into_stream().collect::<Vec<_>>()).len(), 2);
+        let third = aw!(transport.send(request())).err().unwrap();
+        assert_eq!(third.get_kind(), TransportErrorKind::Timeout);
+        assert!(aw!(transport.send(request())).is_err());
+
+        let requests = transport.requests();
+        assert_eq!(requests.len(), 4);
+        assert_eq!(requests[0].json()["model"], "m");
+        assert_eq!(transport.remaining(), 0);
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+
+use serde::de::DeserializeOwned;
+use serde_json::Map;
+use serde_json::Value;
+use serde_json::json;
+
+use crate::error::Error;
+
+pub use xtgptr_derive::JsonSchema;
+
+/// Types with a JSON schema in the strict subset of OpenAI structured outputs: every property is
+/// required, objects take no additional properties and `Option` fields are nullable \
+/// Derive it with `#[derive(JsonSchema)]`, doc comments become descriptions:
+/// ```
+/// use serde::Deserialize;
+/// use xtgptr::schema::JsonSchema;
+///
+/// /// Weather request
+/// #[derive(Deserialize, JsonSchema)]
+/// struct GetWeather {
+///     /// City name, e.g. Paris
+///     city: String,
+///     unit: Option<Unit>
+/// }
+///
+/// #[derive(Deserialize, JsonSchema)]
+/// #[serde(rename_all = "lowercase")]
+/// enum Unit {
+///     Celsius,
+///     Fahrenheit
+/// }
+///
+/// let schema = GetWeather::schema();
+/// assert_eq!(schema["properties"]["city"]["description"], "City name, e.g. Paris");
+/// assert_eq!(schema["required"], serde_json::json!(["city", "unit"]));
+/// ```
+/// Recursive types are not supported, the schema is generated inline.
+pub trait JsonSchema {
+    fn schema() -> Value;
+
+    /// Name of the schema, e.g. for `response_format`, the type name when derived
+    fn schema_name() -> String {
+        String::from("value")
+    }
+}
+
+/// Check `value` against `schema`, supporting the keywords generated by [`JsonSchema`]:
+/// `type`, `enum`, `anyOf`, `properties`, `required`, `additionalProperties: false` and `items` \
+/// The first mismatch is returned as [`Error::SchemaMismatch`] with its JSON path.
+pub fn check(schema: &Value, value: &Value) -> Result<(), Error> {
+    check_at(schema, value, "$")
+}
+
+/// Check `value` against the schema of `T`, then deserialize it
+pub fn from_value<T: JsonSchema + DeserializeOwned>(value: Value) -> Result<T, Error> {
+    check(&T::schema(), &value)?;
+    Ok(serde_json::from_value(value)?)
+}
+
+/// [`from_value`] for JSON text, e.g. the arguments of a tool call
+pub fn from_str<T: JsonSchema + DeserializeOwned>(text: &str) -> Result<T, Error> {
+    from_value(serde_json::from_str(text)?)
+}
+
+fn mismatch(path: &str, reason: impl Into<String>) -> Error {
+    Error::SchemaMismatch { path: path.to_string(), reason: reason.into() }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object"
+    }
+}
+
+fn has_type(value: &Value, ty: &str) -> bool {
+    match ty {
+        "number" => value.is_number(),
+        ty => type_name(value) == ty
+    }
+}
+
+fn check_at(schema: &Value, value: &Value, path: &str) -> Result<(), Error> {
+    if let Some(branches) = schema.get("anyOf").and_then(Value::as_array) {
+        return match branches.iter().any(|branch| check_at(branch, value, path).is_ok()) {
+            true => Ok(()),
+            false => Err(mismatch(path, format!("{} matches none of the allowed schemas", type_name(value))))
+        };
+    }
+    let allowed: Vec<&str> = match schema.get("type") {
+        Some(Value::String(ty)) => vec![ty.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+        _ => Vec::new()
+    };
+    if !allowed.is_empty() && !allowed.iter().any(|ty| has_type(value, ty)) {
+        return Err(mismatch(path, format!("expected {}, got {}", allowed.join(" or "), type_name(value))));
+    }
+    if let Some(options) = schema.get("enum").and_then(Value::as_array) {
+        if !options.contains(value) {
+            return Err(mismatch(path, format!("{} is not one of {}", value, Value::Array(options.clone()))));
+        }
+    }
+    match value {
+        Value::Object(map) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            for name in schema.get("required").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+                if !map.contains_key(name) {
+                    return Err(mismatch(path, format!("missing property {}", name)));
+                }
+            }
+            for (name, item) in map {
+                let at = format!("{}.{}", path, name);
+                match properties.and_then(|p| p.get(name)) {
+                    Some(sub) => check_at(sub, item, &at)?,
+                    None if schema.get("additionalProperties") == Some(&Value::Bool(false)) => return Err(mismatch(&at, "unexpected property")),
+                    None => {}
+                }
+            }
+        },
+        Value::Array(items) => {
+            if let Some(sub) = schema.get("items") {
+                for (i, item) in items.iter().enumerate() {
+                    check_at(sub, item, &format!("{}[{}]", path, i))?;
+                }
+            }
+        },
+        _ => {}
+    }
+    Ok(())
+}
+
+/// `schema` accepting null as well
+pub fn nullable(mut schema: Value) -> Value {
+    let ty = match schema.get("type") {
+        Some(Value::String(ty)) if ty != "null" => ty.clone(),
+        _ => return json!({ "anyOf": [schema, { "type": "null" }] })
+    };
+    schema["type"] = json!([ty, "null"]);
+    if let Some(options) = schema.get_mut("enum").and_then(Value::as_array_mut) {
+        options.push(Value::Null);
+    }
+    schema
+}
+
+macro_rules! primitive_schema {
+    ($kind:literal: $($ty:ty),*) => {
+        $(
+            impl JsonSchema for $ty {
+                fn schema() -> Value {
+                    json!({ "type": $kind })
+                }
+            }
+        )*
+    };
+}
+
+primitive_schema!("string": String, str, char);
+primitive_schema!("boolean": bool);
+primitive_schema!("integer": i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+primitive_schema!("number": f32, f64);
+
+impl<T: JsonSchema + ?Sized> JsonSchema for &T {
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+impl<T: JsonSchema + ?Sized> JsonSchema for Box<T> {
+    fn schema() -> Value {
+        T::schema()
+    }
+}
+
+impl<T: JsonSchema> JsonSchema for Option<T> {
+    fn schema() -> Value {
+        nullable(T::schema())
+    }
+}
+
+macro_rules! array_schema {
+    ($($ty:ident),*) => {
+        $(
+            impl<T: JsonSchema> JsonSchema for $ty<T> {
+                fn schema() -> Value {
+                    json!({ "type": "array", "items": T::schema() })
+                }
+            }
+        )*
+    };
+}
+
+array_schema!(Vec, VecDeque, BTreeSet);
+
+impl<T: JsonSchema> JsonSchema for [T] {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+/// Used by the code `#[derive(JsonSchema)]` expands to
+#[doc(hidden)]
+pub mod __private {
+    pub use serde_json::Value;
+
+    use super::*;
+
+    pub fn describe(mut schema: Value, description: Option<&str>) -> Value {
+        if let (Some(description), Some(map)) = (description, schema.as_object_mut()) {
+            map.insert(String::from("description"), Value::from(description));
+        }
+        schema
+    }
+
+    pub fn null() -> Value {
+        json!({ "type": "null" })
+    }
+
+    pub fn object(description: Option<&str>, properties: Vec<(&str, Value)>) -> Value {
+        let required: Vec<&str> = properties.iter().map(|(name, _)| *name).collect();
+        let properties: Map<String, Value> = properties.iter().map(|(name, schema)| (name.to_string(), schema.clone())).collect();
+        describe(json!({ "type": "object", "properties": properties, "required": required, "additionalProperties": false }), description)
+    }
+
+    pub fn string_enum(description: Option<&str>, values: &[&str]) -> Value {
+        describe(json!({ "type": "string", "enum": values }), description)
+    }
+
+    pub fn any_of(description: Option<&str>, schemas: Vec<Value>) -> Value {
+        describe(json!({ "anyOf": schemas }), description)
+    }
+
+    /// Object `content` with the `tag` property first, for internally tagged enums
+    pub fn tagged(description: Option<&str>, tag: &str, variant: &str, content: Value) -> Value {
+        let mut properties = vec![(tag, string_enum(None, &[variant]))];
+        if let Some(map) = content.get("properties").and_then(Value::as_object) {
+            properties.extend(map.iter().map(|(name, schema)| (name.as_str(), schema.clone())));
+        }
+        describe(object(None, properties), description.or(content.get("description").and_then(Value::as_str)))
+    }
+}
+
+#[cfg(test)]
+mod schema_tests {
+    use serde::Deserialize;
+
+    use super::*;
+
+    /// A city
+    #[derive(Deserialize, JsonSchema, PartialEq, Debug)]
+    #[serde(rename_all = "camelCase")]
+    struct City {
+        /// Name in English
+        city_name: String,
+        population: Option<u64>,
+        #[serde(rename = "tags")]
+        labels: Vec<Label>,
+        #[serde(skip)]
+        _cache: Option<String>
+    }
+
+    #[derive(Deserialize, JsonSchema, PartialEq, Debug)]
+    #[serde(rename_all = "snake_case")]
+    enum Label {
+        Capital,
+        PortCity
+    }
+
+    #[derive(Deserialize, JsonSchema, PartialEq, Debug)]
+    #[serde(tag = "kind", rename_all = "lowercase")]
+    enum Shape {
+        /// A circle
+        Circle { radius: f64 },
+        Square { side: f64 },
+        Point
+    }
+
+    #[test]
+    fn test_derive() {
+        assert_eq!(City::schema(), json!({
+            "type": "object",
+            "description": "A city",
+            "properties": {
+                "cityName": { "type": "string", "description": "Name in English" },
+                "population": { "type": ["integer", "null"] },
+                "tags": { "type": "array", "items": { "type": "string", "enum": ["capital", "port_city"] } }
+            },
+            "required": ["cityName", "population", "tags"],
+            "additionalProperties": false
+        }));
+        assert_eq!(City::schema_name(), "City");
+        assert_eq!(Option::<Label>::schema(), json!({ "type": ["string", "null"], "enum": ["capital", "port_city", null] }));
+
+        let shape = Shape::schema();
+        assert_eq!(shape["anyOf"][0], json!({
+            "type": "object",
+            "description": "A circle",
+            "properties": { "kind": { "type": "string", "enum": ["circle"] }, "radius": { "type": "number" } },
+            "required": ["kind", "radius"],
+            "additionalProperties": false
+        }));
+        assert_eq!(shape["anyOf"][2]["required"], json!(["kind"]));
+    }
+
+    #[test]
+    fn test_check() {
+        let city: City = from_str("{\"cityName\": \"Oslo\", \"population\": null, \"tags\": [\"capital\", \"port_city\"]}").unwrap();
+        assert_eq!(city.labels, vec![Label::Capital, Label::PortCity]);
+
+        let err = from_str::<City>("{\"cityName\": \"Oslo\", \"population\": 7, \"tags\": [\"village\"]}").unwrap_err();
+        assert!(matches!(&err, Error::SchemaMismatch { path, .. } if path == "$.tags[0]"));
+        let err = from_str::<City>("{\"cityName\": \"Oslo\", \"tags\": []}").unwrap_err();
+        assert_eq!(err.to_string(), "schema mismatch at $: missing property population");
+        let err = from_str::<City>("{\"cityName\": \"Oslo\", \"population\": 1.5, \"tags\": []}").unwrap_err();
+        assert_eq!(err.to_string(), "schema mismatch at $.population: expected integer or null, got number");
+        let err = from_str::<City>("{\"cityName\": \"Oslo\", \"population\": 1, \"tags\": [], \"mayor\": \"X\"}").unwrap_err();
+        assert_eq!(err.to_string(), "schema mismatch at $.mayor: unexpected property");
+        assert!(from_str::<City>("{\"cityName\": 1,").is_err());
+
+        assert_eq!(from_str::<Shape>("{\"kind\": \"square\", \"side\": 2}").unwrap(), Shape::Square { side: 2.0 });
+        assert_eq!(from_str::<Shape>("{\"kind\": \"point\"}").unwrap(), Shape::Point);
+        assert!(from_str::<Shape>("{\"kind\": \"circle\", \"side\": 2}").is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt;
+
+use reqwest::header::HeaderValue;
+use serde::Deserialize;
+use serde::Deserializer;
+use zeroize::Zeroize;
+
+use crate::error::Error;
+
+/// A key or token that must not leak \
+/// Debug and Display only show a preview like `sk-...abcd`, the memory is zeroed on drop,
+/// and header values built from it are marked sensitive. Call [`expose`](Secret::expose) to get the value itself.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret {
+    value: String
+}
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Secret {
+        Secret { value: value.into() }
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.value
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.value.is_empty()
+    }
+
+    /// First 3 and last 4 characters, or only "***" when the value is too short to hide anything
+    pub fn preview(&self) -> String {
+        let chars: Vec<char> = self.value.chars().collect();
+        match chars.len() < 12 {
+            true => String::from("***"),
+            false => format!("{}...{}", chars[..3].iter().collect::<String>(), chars[chars.len() - 4..].iter().collect::<String>())
+        }
+    }
+
+    /// `{prefix}{value}` as a header value marked sensitive, so it is hidden from reqwest's and http's Debug output
+    pub fn header_value(&self, prefix: &str) -> Result<HeaderValue, Error> {
+        let mut raw = format!("{}{}", prefix, self.value);
+        let value = HeaderValue::from_str(&raw);
+        raw.zeroize();
+        let mut value = value.map_err(|_| Error::validation("auth", "Auth is not legal header value"))?;
+        value.set_sensitive(true);
+        Ok(value)
+    }
+}
+
+impl Drop for Secret {
+    fn drop(&mut self) {
+        self.value.zeroize();
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret({})", self.preview())
+    }
+}
+
+impl fmt::Display for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.preview())
+    }
+}
+
+impl From<String> for Secret {
+    fn from(value: String) -> Self {
+        Secret::new(value)
+    }
+}
+
+impl From<&str> for Secret {
+    fn from(value: &str) -> Self {
+        Secret::new(value)
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Secret::new)
+    }
+}
+
+#[cfg(test)]
+mod secret_tests {
+    use super::*;
+
+    #[test]
+    fn test_redacted() {
+        let secret = Secret::new("sk-proj-XXXXXXXXXXXXXXXXXXXXabcd");
+        assert_eq!(secret.preview(), "sk-...abcd");
+        assert_eq!(format!("{}", secret), "sk-...abcd");
+        assert_eq!(format!("{:?}", secret), "Secret(sk-...abcd)");
+        assert!(!format!("{:?}", Some(&secret)).contains("XXXX"));
+        assert_eq!(Secret::new("short").preview(), "***");
+        assert_eq!(secret.expose(), "sk-proj-XXXXXXXXXXXXXXXXXXXXabcd");
+    }
+
+    #[test]
+    fn test_header_value() {
+        let value = Secret::new("sk-XXXXXXXXXXXXabcd").header_value("Bearer ").unwrap();
+        assert!(value.is_sensitive());
+        assert_eq!(value, "Bearer sk-XXXXXXXXXXXXabcd");
+        assert_eq!(format!("{:?}", value), "Sensitive");
+        assert!(Secret::new("bad\nkey").header_value("").is_err());
+    }
+}
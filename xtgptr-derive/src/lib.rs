@@ -0,0 +1,273 @@
+//! Derive macros of `xtgptr`, use them through the re-exports of the main crate (`xtgptr::schema::JsonSchema`)
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::meta::ParseNestedMeta;
+use syn::parse_macro_input;
+use syn::parse_quote;
+use syn::Attribute;
+use syn::Data;
+use syn::DeriveInput;
+use syn::Expr;
+use syn::Fields;
+use syn::FieldsNamed;
+use syn::Lit;
+use syn::LitStr;
+use syn::Meta;
+use syn::Token;
+
+const RENAME_RULES: &[&str] = &["lowercase", "UPPERCASE", "PascalCase", "camelCase", "snake_case", "SCREAMING_SNAKE_CASE", "kebab-case", "SCREAMING-KEBAB-CASE"];
+
+/// Implement `xtgptr::schema::JsonSchema` with a strict JSON schema of the type \
+/// Doc comments become descriptions. The `serde` attributes `rename`, `rename_all`, `tag`, `content`,
+/// `untagged`, `skip` and `transparent` are followed so the schema describes what serde reads.
+#[proc_macro_derive(JsonSchema, attributes(serde))]
+pub fn derive_json_schema(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(gets) => gets.into(),
+        Err(e) => e.to_compile_error().into()
+    }
+}
+
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    tag: Option<String>,
+    content: Option<String>,
+    untagged: bool,
+    skip: bool,
+    flatten: bool,
+    transparent: bool
+}
+
+/// Value of `name = "..."`, or of `deserialize = "..."` in `name(serialize = "...", deserialize = "...")`
+fn renamed(meta: &ParseNestedMeta) -> syn::Result<Option<String>> {
+    if meta.input.peek(Token![=]) {
+        return Ok(Some(meta.value()?.parse::<LitStr>()?.value()));
+    }
+    let mut gets = None;
+    meta.parse_nested_meta(|inner| {
+        match inner.path.is_ident("deserialize") {
+            true => gets = Some(inner.value()?.parse::<LitStr>()?.value()),
+            false => skip(&inner)?
+        }
+        Ok(())
+    })?;
+    Ok(gets)
+}
+
+fn skip(meta: &ParseNestedMeta) -> syn::Result<()> {
+    if meta.input.peek(Token![=]) {
+        meta.value()?.parse::<Expr>()?;
+    } else if meta.input.peek(syn::token::Paren) {
+        meta.parse_nested_meta(|inner| skip(&inner))?;
+    }
+    Ok(())
+}
+
+fn serde_attrs(attrs: &[Attribute]) -> syn::Result<SerdeAttrs> {
+    let mut gets = SerdeAttrs::default();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("rename") {
+                gets.rename = renamed(&meta)?;
+            } else if meta.path.is_ident("rename_all") {
+                gets.rename_all = renamed(&meta)?;
+                if let Some(rule) = &gets.rename_all {
+                    if !RENAME_RULES.contains(&rule.as_str()) {
+                        return Err(meta.error(format!("unknown rename rule {:?}", rule)));
+                    }
+                }
+            } else if meta.path.is_ident("tag") {
+                gets.tag = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("content") {
+                gets.content = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("untagged") {
+                gets.untagged = true;
+            } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing") {
+                gets.skip = true;
+            } else if meta.path.is_ident("flatten") {
+                gets.flatten = true;
+            } else if meta.path.is_ident("transparent") {
+                gets.transparent = true;
+            } else {
+                skip(&meta)?;
+            }
+            Ok(())
+        })?;
+    }
+    Ok(gets)
+}
+
+/// Doc comment lines joined with spaces, `Option<&str>` tokens
+fn docs(attrs: &[Attribute]) -> TokenStream2 {
+    let lines: Vec<String> = attrs.iter()
+        .filter(|a| a.path().is_ident("doc"))
+        .filter_map(|a| match &a.meta {
+            Meta::NameValue(nv) => match &nv.value {
+                Expr::Lit(lit) => match &lit.lit {
+                    Lit::Str(s) => Some(s.value()),
+                    _ => None
+                },
+                _ => None
+            },
+            _ => None
+        })
+        .map(|line| line.trim().trim_end_matches('\\').trim_end().to_string())
+        .filter(|line| !line.is_empty())
+        .collect();
+    match lines.is_empty() {
+        true => quote!(::core::option::Option::None),
+        false => {
+            let text = lines.join(" ");
+            quote!(::core::option::Option::Some(#text))
+        }
+    }
+}
+
+/// serde's rename rules, `variant` names are PascalCase, field names snake_case
+fn rename(name: &str, rule: Option<&str>, variant: bool) -> String {
+    let snake = match variant {
+        true => {
+            let mut snake = String::new();
+            for (i, c) in name.chars().enumerate() {
+                if i > 0 && c.is_uppercase() {
+                    snake.push('_');
+                }
+                snake.push(c.to_ascii_lowercase());
+            }
+            snake
+        },
+        false => name.to_string()
+    };
+    let pascal = match variant {
+        true => name.to_string(),
+        false => snake.split('_').map(|w| {
+            let mut chars = w.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new()
+            }
+        }).collect()
+    };
+    match rule {
+        Some("lowercase") => name.to_ascii_lowercase(),
+        Some("UPPERCASE") => name.to_ascii_uppercase(),
+        Some("PascalCase") => pascal,
+        Some("camelCase") => {
+            let mut chars = pascal.chars();
+            match chars.next() {
+                Some(first) => first.to_lowercase().chain(chars).collect(),
+                None => String::new()
+            }
+        },
+        Some("snake_case") => snake,
+        Some("SCREAMING_SNAKE_CASE") => snake.to_ascii_uppercase(),
+        Some("kebab-case") => snake.replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => snake.replace('_', "-").to_ascii_uppercase(),
+        _ => name.to_string()
+    }
+}
+
+fn object(fields: &FieldsNamed, rename_all: Option<&str>, description: TokenStream2) -> syn::Result<TokenStream2> {
+    let mut properties = Vec::new();
+    for field in &fields.named {
+        let attrs = serde_attrs(&field.attrs)?;
+        if attrs.skip {
+            continue;
+        }
+        if attrs.flatten {
+            return Err(syn::Error::new_spanned(field, "JsonSchema does not support #[serde(flatten)]"));
+        }
+        let ident = field.ident.as_ref().map(|i| i.to_string()).unwrap_or_default();
+        let name = attrs.rename.unwrap_or_else(|| rename(ident.trim_start_matches("r#"), rename_all, false));
+        let ty = &field.ty;
+        let doc = docs(&field.attrs);
+        properties.push(quote!((#name, ::xtgptr::schema::__private::describe(<#ty as ::xtgptr::schema::JsonSchema>::schema(), #doc))));
+    }
+    Ok(quote!(::xtgptr::schema::__private::object(#description, ::std::vec![#(#properties),*])))
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let container = serde_attrs(&input.attrs)?;
+    let description = docs(&input.attrs);
+    let schema = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) if !container.transparent => object(fields, container.rename_all.as_deref(), description)?,
+            Fields::Named(fields) if fields.named.len() == 1 => {
+                let ty = &fields.named[0].ty;
+                quote!(::xtgptr::schema::__private::describe(<#ty as ::xtgptr::schema::JsonSchema>::schema(), #description))
+            },
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                quote!(::xtgptr::schema::__private::describe(<#ty as ::xtgptr::schema::JsonSchema>::schema(), #description))
+            },
+            _ => return Err(syn::Error::new_spanned(&input.ident, "JsonSchema needs named fields or a single field"))
+        },
+        Data::Enum(data) => {
+            let mut variants = Vec::new();
+            for variant in &data.variants {
+                let attrs = serde_attrs(&variant.attrs)?;
+                if attrs.skip {
+                    continue;
+                }
+                let name = attrs.rename.unwrap_or_else(|| rename(&variant.ident.to_string(), container.rename_all.as_deref(), true));
+                variants.push((name, variant, attrs.rename_all));
+            }
+            let plain = !container.untagged && container.tag.is_none() && variants.iter().all(|(_, v, _)| matches!(v.fields, Fields::Unit));
+            if plain {
+                let names = variants.iter().map(|(name, _, _)| name);
+                quote!(::xtgptr::schema::__private::string_enum(#description, &[#(#names),*]))
+            } else {
+                let mut schemas = Vec::new();
+                for (name, variant, rename_all) in &variants {
+                    let doc = docs(&variant.attrs);
+                    let none = quote!(::core::option::Option::None);
+                    let content = match &variant.fields {
+                        Fields::Unit => None,
+                        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                            let ty = &fields.unnamed[0].ty;
+                            Some(quote!(<#ty as ::xtgptr::schema::JsonSchema>::schema()))
+                        },
+                        Fields::Named(fields) => Some(object(fields, rename_all.as_deref(), none.clone())?),
+                        Fields::Unnamed(_) => return Err(syn::Error::new_spanned(variant, "JsonSchema does not support tuple variants"))
+                    };
+                    let tag_value = quote!(::xtgptr::schema::__private::string_enum(#none, &[#name]));
+                    schemas.push(match (container.untagged, &container.tag, &container.content, content) {
+                        (true, _, _, None) => quote!(::xtgptr::schema::__private::describe(::xtgptr::schema::__private::null(), #doc)),
+                        (true, _, _, Some(content)) => quote!(::xtgptr::schema::__private::describe(#content, #doc)),
+                        (false, Some(tag), Some(key), Some(content)) => quote!(::xtgptr::schema::__private::object(#doc, ::std::vec![(#tag, #tag_value), (#key, #content)])),
+                        (false, Some(tag), _, None) => quote!(::xtgptr::schema::__private::object(#doc, ::std::vec![(#tag, #tag_value)])),
+                        (false, Some(tag), None, Some(content)) => quote!(::xtgptr::schema::__private::tagged(#doc, #tag, #name, #content)),
+                        (false, None, _, None) => quote!(::xtgptr::schema::__private::string_enum(#doc, &[#name])),
+                        (false, None, _, Some(content)) => quote!(::xtgptr::schema::__private::object(#doc, ::std::vec![(#name, #content)]))
+                    });
+                }
+                quote!(::xtgptr::schema::__private::any_of(#description, ::std::vec![#(#schemas),*]))
+            }
+        },
+        Data::Union(_) => return Err(syn::Error::new_spanned(&input.ident, "JsonSchema does not support unions"))
+    };
+
+    let ident = &input.ident;
+    let name = container.rename.unwrap_or_else(|| ident.to_string());
+    let mut generics = input.generics.clone();
+    for param in generics.type_params_mut() {
+        param.bounds.push(parse_quote!(::xtgptr::schema::JsonSchema));
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::xtgptr::schema::JsonSchema for #ident #ty_generics #where_clause {
+            fn schema() -> ::xtgptr::schema::__private::Value {
+                #schema
+            }
+
+            fn schema_name() -> ::std::string::String {
+                ::std::string::String::from(#name)
+            }
+        }
+    })
+}